@@ -0,0 +1,90 @@
+//! StreamPipe throughput against the `Mutex<Vec<u8>>` pipe it replaced.
+//!
+//! cargo bench --bench stream_pipe
+use radico::audio::stream::StreamPipe;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// about 5 s of a 48 kbps stream
+const SEGMENT: usize = 31_000;
+const SEGMENTS: usize = 200;
+/// read size of redlux
+const READ: usize = 4096;
+
+/// the previous implementation
+#[derive(Clone, Default)]
+struct MutexPipe {
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Read for MutexPipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut vec = self.buffer.lock().unwrap();
+        let to_read = vec.len().min(buf.len());
+        if to_read == 0 {
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+        buf[..to_read].copy_from_slice(&vec[..to_read]);
+        vec.drain(..to_read);
+        Ok(to_read)
+    }
+}
+
+trait Pipe: Read + Clone + Send + 'static {
+    fn push(&mut self, data: &[u8]);
+}
+
+impl Pipe for MutexPipe {
+    fn push(&mut self, data: &[u8]) {
+        self.buffer.lock().unwrap().extend(data);
+    }
+}
+
+impl Pipe for StreamPipe {
+    fn push(&mut self, data: &[u8]) {
+        self.add(data);
+    }
+}
+
+/// `backlog` segments are buffered up front, then a producer thread adds
+/// the rest while the consumer drains everything
+fn run<P: Pipe>(pipe: P, backlog: usize) -> Duration {
+    let data = vec![0xA5_u8; SEGMENT];
+    let mut producer = pipe.clone();
+    for _ in 0..backlog {
+        producer.push(&data);
+    }
+
+    let start = Instant::now();
+    let handle = thread::spawn(move || {
+        for _ in 0..SEGMENTS {
+            producer.push(&data);
+            thread::yield_now();
+        }
+    });
+
+    let mut consumer = pipe;
+    let mut buf = [0; READ];
+    let mut total = 0;
+    while total < SEGMENT * (SEGMENTS + backlog) {
+        match consumer.read(&mut buf) {
+            Ok(n) => total += n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::yield_now(),
+            Err(e) => panic!("{e}"),
+        }
+    }
+    handle.join().unwrap();
+    start.elapsed()
+}
+
+fn main() {
+    let capacity = SEGMENT * SEGMENTS * 2;
+    println!("{:>8} {:>14} {:>14}", "backlog", "Mutex<Vec>", "StreamPipe");
+    for backlog in [0, 8, 32, 128] {
+        let mutex = run(MutexPipe::default(), backlog);
+        let ring = run(StreamPipe::new(capacity), backlog);
+        println!("{:>8} {:>14.2?} {:>14.2?}", backlog, mutex, ring);
+    }
+}
@@ -1,9 +1,14 @@
 //! AAC decoder for MPEG-4 (MP4, M4A etc) and AAC files. Supports rodio.
 use fdk_aac::dec::{Decoder as AacDecoder, DecoderError, Transport};
-use std::io::Read;
+use std::io::{Read, Seek};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{error, fmt, io};
 
+mod mpeg4;
+pub use mpeg4::{remux_fragment, Mpeg4Reader};
+
 /// Redlux error
 #[derive(Debug)]
 pub enum Error {
@@ -22,6 +27,10 @@ pub enum Error {
   SamplesError,
   /// Error from the underlying reader R
   ReaderError(io::Error),
+  /// The reader has no data available yet (`io::ErrorKind::WouldBlock`)
+  Underrun,
+  /// The reader is exhausted
+  EndOfStream,
 }
 
 impl error::Error for Error {}
@@ -36,6 +45,8 @@ impl Error {
       Error::TrackDecodingError(_) => "Error decoding track",
       Error::SamplesError => "Error reading samples",
       Error::ReaderError(_) => "Error reading file",
+      Error::Underrun => "No data available yet",
+      Error::EndOfStream => "End of stream",
     }
   }
 }
@@ -49,6 +60,7 @@ impl fmt::Display for Error {
 /// File container format
 pub enum Format {
   Aac,
+  Mpeg4,
 }
 
 /// Underlying reader
@@ -56,15 +68,63 @@ pub enum Reader<R> {
   AacReader(R),
 }
 
+/// Decoder counters, shared with the caller through [`Decoder::stats`]
+#[derive(Debug, Default)]
+pub struct Stats {
+  underruns: AtomicU64,
+  corrupted_frames: AtomicU64,
+  resyncs: AtomicU64,
+  starving: AtomicBool,
+  ended: AtomicBool,
+}
+
+impl Stats {
+  /// Number of times the reader ran dry while playing
+  pub fn underruns(&self) -> u64 {
+    self.underruns.load(Ordering::Relaxed)
+  }
+  /// Number of frames that failed to decode
+  pub fn corrupted_frames(&self) -> u64 {
+    self.corrupted_frames.load(Ordering::Relaxed)
+  }
+  /// Number of times the decoder lost ADTS sync
+  pub fn resyncs(&self) -> u64 {
+    self.resyncs.load(Ordering::Relaxed)
+  }
+  /// The decoder is outputting silence while waiting for data
+  pub fn is_starving(&self) -> bool {
+    self.starving.load(Ordering::Relaxed)
+  }
+  /// The reader reached its end
+  pub fn is_ended(&self) -> bool {
+    self.ended.load(Ordering::Relaxed)
+  }
+}
+
+/// Samples per channel of silence emitted per underrun or corrupted frame
+const SILENCE_FRAMES: usize = 1024;
+/// Size of reads from the underlying reader
+const READ_LEN: usize = 4096;
+/// Room for 2048 samples (HE-AAC) of 8 channels
+const PCM_LEN: usize = 2048 * 8;
+/// Format assumed until the first frame is decoded
+const DEFAULT_CHANNELS: u16 = 2;
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
 pub struct Decoder<R>
 where
     R: Read,
 {
   reader: Reader<R>,
+  format: Format,
   aac_decoder: AacDecoder,
   bytes: Vec<u8>,
   current_pcm_index: usize,
   current_pcm: Vec<i16>,
+  /// format of current_pcm, may change between frames (e.g. SBR/PS)
+  current_channels: u16,
+  current_sample_rate: u32,
+  stats: Arc<Stats>,
   /// If there's an error while iterating over the Decoder, that error is added here
   pub iter_error: Option<Error>,
 }
@@ -73,83 +133,159 @@ impl<R> Decoder<R>
 where
     R: Read,
 {
-  /// Create from an aac buffer
+  /// Create from an aac buffer. A reader returning `io::ErrorKind::WouldBlock`
+  /// is treated as live: the decoder outputs silence until data arrives,
+  /// while `Ok(0)` ends the stream. The first frame is decoded here, so the
+  /// format is known before the decoder is handed to a sink.
   pub fn new_aac(reader: R) -> Self {
     let aac_decoder = AacDecoder::new(Transport::Adts);
-    Decoder {
+    let mut decoder = Decoder {
       reader: Reader::AacReader(reader),
+      format: Format::Aac,
       aac_decoder,
       bytes: Vec::new(),
       current_pcm_index: 0,
       current_pcm: Vec::new(),
+      current_channels: DEFAULT_CHANNELS,
+      current_sample_rate: DEFAULT_SAMPLE_RATE,
+      stats: Arc::new(Stats::default()),
       iter_error: None,
-    }
+    };
+    // a live reader may have nothing yet: silence in the default format
+    // then, which is not an underrun
+    decoder.stats.starving.store(true, Ordering::Relaxed);
+    decoder.refill();
+    decoder
   }
 
+  /// Container format of the source
+  pub fn format(&self) -> &Format {
+    &self.format
+  }
+
+  /// Counters that stay readable after the decoder is handed to rodio
+  pub fn stats(&self) -> Arc<Stats> {
+    Arc::clone(&self.stats)
+  }
+
+  /// Samples left in the current frame. The next frame is decoded as soon as
+  /// the current one is consumed, so at a frame boundary this, `channels`
+  /// and `sample_rate` already describe the upcoming frame. Never `None`,
+  /// which would make rodio keep the format it saw first.
   #[inline]
   pub fn current_frame_len(&self) -> Option<usize> {
-    let frame_size: usize = self.aac_decoder.decoded_frame_size();
-    Some(frame_size)
+    Some(self.current_pcm.len() - self.current_pcm_index)
   }
   #[inline]
   pub fn channels(&self) -> u16 {
-    let num_channels: i32 = self.aac_decoder.stream_info().numChannels;
-    num_channels as _
+    self.current_channels
   }
   #[inline]
   pub fn sample_rate(&self) -> u32 {
-    let sample_rate: i32 = self.aac_decoder.stream_info().sampleRate;
-    sample_rate as _
+    self.current_sample_rate
   }
   #[inline]
   pub fn total_duration(&self) -> Option<Duration> {
     None
   }
-  /// Consume and return the next sample, or None when finished
-  #[inline]
-  pub fn decode_next_sample(&mut self) -> Result<Option<i16>, Error> {
-    if self.current_pcm_index == self.current_pcm.len() {
-      if self.current_frame_len().unwrap() == 0 && !self.current_pcm_index == 0 {
-        return Ok(None);
-      }
 
-      let mut pcm = vec![0; 8192];
-      let result = match self.aac_decoder.decode_frame(&mut pcm) {
-        Err(DecoderError::NOT_ENOUGH_BITS) | Err(DecoderError::TRANSPORT_SYNC_ERROR) => {
-          match &mut self.reader {
-            // aac
-            Reader::AacReader(aac_reader) => {
-              let old_bytes_len = self.bytes.len();
-              let mut new_bytes = vec![0; 8192 - old_bytes_len];
-              let bytes_read = match aac_reader.read(&mut new_bytes) {
-                Ok(bytes_read) => bytes_read,
-                Err(err) => return Err(Error::ReaderError(err)),
-              };
-              if bytes_read == 0 {
-                return Err(Error::SamplesError);
-              }
-              // aac files already have adts headers
-              self.bytes.extend(new_bytes);
-            }
-          }
-          let bytes_filled = match self.aac_decoder.fill(&self.bytes) {
-            Ok(bytes_filled) => bytes_filled,
-            Err(err) => return Err(Error::TrackDecodingError(err)),
+  /// Feed the next chunk of the reader to the AAC decoder
+  fn fill(&mut self) -> Result<(), Error> {
+    if self.bytes.is_empty() {
+      match &mut self.reader {
+        // aac files already have adts headers
+        Reader::AacReader(aac_reader) => {
+          let mut new_bytes = vec![0; READ_LEN];
+          let bytes_read = match aac_reader.read(&mut new_bytes) {
+            Ok(0) => return Err(Error::EndOfStream),
+            Ok(bytes_read) => bytes_read,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Err(Error::Underrun),
+            Err(err) => return Err(Error::ReaderError(err)),
           };
-          self.bytes = self.bytes[bytes_filled..].to_vec();
-          self.aac_decoder.decode_frame(&mut pcm)
+          new_bytes.truncate(bytes_read);
+          self.bytes = new_bytes;
+        }
+      }
+    }
+    let bytes_filled = match self.aac_decoder.fill(&self.bytes) {
+      Ok(bytes_filled) => bytes_filled,
+      Err(err) => return Err(Error::TrackDecodingError(err)),
+    };
+    self.bytes.drain(..bytes_filled);
+    Ok(())
+  }
+
+  /// Decode the next frame into current_pcm, false at the end of the stream
+  fn decode_frame(&mut self) -> Result<bool, Error> {
+    let mut pcm = vec![0; PCM_LEN];
+    loop {
+      match self.aac_decoder.decode_frame(&mut pcm) {
+        Ok(()) => break,
+        Err(DecoderError::NOT_ENOUGH_BITS) => {}
+        Err(DecoderError::TRANSPORT_SYNC_ERROR) => {
+          self.stats.resyncs.fetch_add(1, Ordering::Relaxed);
+        }
+        Err(err) => return Err(Error::TrackDecodingError(err)),
+      }
+      match self.fill() {
+        Err(Error::EndOfStream) => return Ok(false),
+        result => result?,
+      }
+    }
+    let info = self.aac_decoder.stream_info();
+    if info.numChannels <= 0 || info.sampleRate <= 0 {
+      return Err(Error::TrackReadingError);
+    }
+    self.current_channels = info.numChannels as u16;
+    self.current_sample_rate = info.sampleRate as u32;
+
+    pcm.truncate(self.aac_decoder.decoded_frame_size());
+    if pcm.is_empty() {
+      return Err(Error::SamplesError);
+    }
+    self.current_pcm = pcm;
+    self.current_pcm_index = 0;
+    self.stats.starving.store(false, Ordering::Relaxed);
+    Ok(true)
+  }
+
+  /// Replace the current frame with silence in the last known format
+  fn silence(&mut self) {
+    self.current_pcm = vec![0; SILENCE_FRAMES * self.current_channels as usize];
+    self.current_pcm_index = 0;
+  }
+
+  /// Prepare the next frame for the iterator: decoded audio, silence while
+  /// the reader has no data or the frame is corrupted, nothing at the end
+  fn refill(&mut self) {
+    match self.decode_frame() {
+      Ok(true) => {}
+      Ok(false) => {
+        self.stats.ended.store(true, Ordering::Relaxed);
+        self.current_pcm.clear();
+        self.current_pcm_index = 0;
+      }
+      Err(Error::Underrun) => {
+        if !self.stats.starving.swap(true, Ordering::Relaxed) {
+          self.stats.underruns.fetch_add(1, Ordering::Relaxed);
         }
-        val => val,
-      };
-      if let Err(err) = result {
-        return Err(Error::TrackDecodingError(err));
+        self.silence();
       }
-      let decoded_frame_size = self.aac_decoder.decoded_frame_size();
-      if decoded_frame_size < pcm.len() {
-        let _ = pcm.split_off(decoded_frame_size);
+      Err(err) => {
+        if let Error::TrackDecodingError(_) = err {
+          self.stats.corrupted_frames.fetch_add(1, Ordering::Relaxed);
+        }
+        self.iter_error = Some(err);
+        self.silence();
       }
-      self.current_pcm = pcm;
-      self.current_pcm_index = 0;
+    }
+  }
+
+  /// Consume and return the next sample, or None when finished
+  #[inline]
+  pub fn decode_next_sample(&mut self) -> Result<Option<i16>, Error> {
+    if self.current_pcm_index == self.current_pcm.len() && !self.decode_frame()? {
+      return Ok(None);
     }
     let value = self.current_pcm[self.current_pcm_index];
     self.current_pcm_index += 1;
@@ -158,23 +294,43 @@ where
   }
 }
 
+impl<R> Decoder<Mpeg4Reader<R>>
+where
+    R: Read + Seek,
+{
+  /// Create from an MP4/M4A container, fragmented or not, decoding its
+  /// first AAC track
+  pub fn new_mpeg4(reader: R, size: u64) -> Result<Self, Error> {
+    let mut decoder = Decoder::new_aac(Mpeg4Reader::new(reader, size)?);
+    decoder.format = Format::Mpeg4;
+    Ok(decoder)
+  }
+}
+
 impl<R> Iterator for Decoder<R>
 where
     R: Read,
 {
   type Item = i16;
-  /// Runs decode_next_sample and returns the sample from that. Once the
-  /// iterator is finished, it returns None. If there's an error, it's added
-  /// to the iter_error error.
+  /// Returns the next sample and decodes ahead at frame boundaries, so format
+  /// changes line up with `current_frame_len`. Once the stream is finished,
+  /// it returns None. While the reader has no data, and on decoding errors,
+  /// it returns silence; errors are added to iter_error and counted in
+  /// [`Stats`].
   #[inline]
   fn next(&mut self) -> Option<i16> {
-    match self.decode_next_sample() {
-      Ok(sample) => sample,
-      Err(err) => {
-        self.iter_error = Some(err);
-        Some(0)
+    if self.current_pcm_index == self.current_pcm.len() {
+      if self.stats.is_ended() {
+        return None;
       }
+      self.refill();
+    }
+    let value = *self.current_pcm.get(self.current_pcm_index)?;
+    self.current_pcm_index += 1;
+    if self.current_pcm_index == self.current_pcm.len() {
+      self.refill();
     }
+    Some(value)
   }
 }
 
@@ -0,0 +1,191 @@
+//! Reads AAC tracks from MPEG-4 containers and re-frames them with ADTS headers
+use crate::Error;
+use mp4::{MediaType, Mp4Reader, Mp4Track};
+use std::io::{self, Cursor, Read, Seek};
+
+/// ADTS header parameters of an AAC track
+#[derive(Clone, Copy, Debug)]
+struct AdtsConfig {
+  profile: u8,
+  freq_index: u8,
+  channels: u8,
+}
+
+impl AdtsConfig {
+  fn from_track(track: &Mp4Track) -> Result<Self, Error> {
+    let aot = track.audio_profile().or(Err(Error::TrackReadingError))? as u8;
+    // HE-AAC is signalled as AAC-LC, fdk-aac detects SBR/PS implicitly
+    let profile = match aot {
+      1 => 0,
+      2 | 5 | 29 => 1,
+      3 => 2,
+      4 => 3,
+      _ => return Err(Error::UnsupportedObjectType),
+    };
+    Ok(AdtsConfig {
+      profile,
+      freq_index: track.sample_freq_index().or(Err(Error::TrackReadingError))? as u8,
+      channels: track.channel_config().or(Err(Error::TrackReadingError))? as u8,
+    })
+  }
+
+  /// 7 byte ADTS header without CRC for a raw frame of `len` bytes
+  fn header(&self, len: usize) -> [u8; 7] {
+    let frame_len = len + 7;
+    [
+      0xFF,
+      0xF1,
+      (self.profile << 6) | (self.freq_index << 2) | (self.channels >> 2),
+      ((self.channels & 3) << 6) | (frame_len >> 11) as u8,
+      ((frame_len >> 3) & 0xFF) as u8,
+      (((frame_len & 7) << 5) | 0x1F) as u8,
+      0xFC,
+    ]
+  }
+}
+
+fn aac_track<R>(mp4: &Mp4Reader<R>) -> Result<(u32, AdtsConfig), Error>
+where
+    R: Read + Seek,
+{
+  let track = mp4
+    .tracks()
+    .values()
+    .find(|x| matches!(x.media_type(), Ok(MediaType::AAC)))
+    .ok_or(Error::TrackNotFound)?;
+  Ok((track.track_id(), AdtsConfig::from_track(track)?))
+}
+
+/// `Read` adapter producing an ADTS stream from the first AAC track of an
+/// MP4/M4A file, fragmented or not
+pub struct Mpeg4Reader<R> {
+  mp4: Mp4Reader<R>,
+  track_id: u32,
+  config: AdtsConfig,
+  sample_id: u32,
+  sample_count: u32,
+  frame: Vec<u8>,
+  position: usize,
+}
+
+impl<R> Mpeg4Reader<R>
+where
+    R: Read + Seek,
+{
+  pub fn new(reader: R, size: u64) -> Result<Self, Error> {
+    let mp4 = Mp4Reader::read_header(reader, size).or(Err(Error::FileHeaderError))?;
+    let (track_id, config) = aac_track(&mp4)?;
+    let sample_count = mp4.sample_count(track_id).or(Err(Error::TrackReadingError))?;
+    Ok(Mpeg4Reader {
+      mp4,
+      track_id,
+      config,
+      sample_id: 1,
+      sample_count,
+      frame: Vec::new(),
+      position: 0,
+    })
+  }
+
+  /// Read the next sample as an ADTS frame, None at the end of the track
+  pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+    while self.sample_id <= self.sample_count {
+      let sample = self
+        .mp4
+        .read_sample(self.track_id, self.sample_id)
+        .or(Err(Error::SamplesError))?;
+      self.sample_id += 1;
+      if let Some(sample) = sample {
+        let mut frame = self.config.header(sample.bytes.len()).to_vec();
+        frame.extend_from_slice(&sample.bytes);
+        return Ok(Some(frame));
+      }
+    }
+    Ok(None)
+  }
+}
+
+impl<R> Read for Mpeg4Reader<R>
+where
+    R: Read + Seek,
+{
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if self.position == self.frame.len() {
+      match self.next_frame() {
+        Ok(Some(frame)) => {
+          self.frame = frame;
+          self.position = 0;
+        }
+        Ok(None) => return Ok(0),
+        Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+      }
+    }
+    let to_read = (self.frame.len() - self.position).min(buf.len());
+    buf[..to_read].copy_from_slice(&self.frame[self.position..self.position + to_read]);
+    self.position += to_read;
+    Ok(to_read)
+  }
+}
+
+/// Remux one fragment of a fragmented MP4 stream (e.g. an fMP4 HLS segment)
+/// to ADTS, using the track info of its initialization segment
+pub fn remux_fragment(init: &[u8], fragment: &[u8]) -> Result<Vec<u8>, Error> {
+  let header =
+    Mp4Reader::read_header(Cursor::new(init), init.len() as u64).or(Err(Error::FileHeaderError))?;
+  let (track_id, config) = aac_track(&header)?;
+  let mut mp4 = header
+    .read_fragment_header(Cursor::new(fragment), fragment.len() as u64)
+    .or(Err(Error::FileHeaderError))?;
+  let sample_count = mp4.sample_count(track_id).or(Err(Error::TrackReadingError))?;
+
+  let mut adts = Vec::with_capacity(fragment.len() + sample_count as usize * 7);
+  for sample_id in 1..=sample_count {
+    if let Some(sample) = mp4
+      .read_sample(track_id, sample_id)
+      .or(Err(Error::SamplesError))?
+    {
+      adts.extend_from_slice(&config.header(sample.bytes.len()));
+      adts.extend_from_slice(&sample.bytes);
+    }
+  }
+  Ok(adts)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// AAC-LC, 44.1 kHz, stereo
+  const CONFIG: AdtsConfig = AdtsConfig {
+    profile: 1,
+    freq_index: 4,
+    channels: 2,
+  };
+
+  #[test]
+  fn header() {
+    assert_eq!(CONFIG.header(100), [0xFF, 0xF1, 0x50, 0x80, 0x0D, 0x7F, 0xFC]);
+    // the largest frame fills all 13 length bits
+    assert_eq!(CONFIG.header(8184)[3..6], [0x83, 0xFF, 0xFF]);
+  }
+
+  #[test]
+  fn remux() {
+    // one AAC-LC track, and a fragment of three samples of 10, 20 and 16 bytes
+    let init = include_bytes!("../tests/fixtures/init.mp4");
+    let fragment = include_bytes!("../tests/fixtures/fragment.m4s");
+    let mdat = &fragment[fragment.len() - 46..];
+
+    let mut expected = Vec::new();
+    let mut offset = 0;
+    for len in [10, 20, 16] {
+      expected.extend_from_slice(&CONFIG.header(len));
+      expected.extend_from_slice(&mdat[offset..offset + len]);
+      offset += len;
+    }
+    assert_eq!(remux_fragment(init, fragment).unwrap(), expected);
+
+    assert!(remux_fragment(init, b"not a fragment").is_err());
+    assert!(remux_fragment(b"not an init segment", fragment).is_err());
+  }
+}
@@ -0,0 +1,59 @@
+/// radiko areas, one per prefecture, named as the area check reports them
+pub const AREAS: [(&str, &str); 47] = [
+    ("JP1", "HOKKAIDO JAPAN"),
+    ("JP2", "AOMORI JAPAN"),
+    ("JP3", "IWATE JAPAN"),
+    ("JP4", "MIYAGI JAPAN"),
+    ("JP5", "AKITA JAPAN"),
+    ("JP6", "YAMAGATA JAPAN"),
+    ("JP7", "FUKUSHIMA JAPAN"),
+    ("JP8", "IBARAKI JAPAN"),
+    ("JP9", "TOCHIGI JAPAN"),
+    ("JP10", "GUNMA JAPAN"),
+    ("JP11", "SAITAMA JAPAN"),
+    ("JP12", "CHIBA JAPAN"),
+    ("JP13", "TOKYO JAPAN"),
+    ("JP14", "KANAGAWA JAPAN"),
+    ("JP15", "NIIGATA JAPAN"),
+    ("JP16", "TOYAMA JAPAN"),
+    ("JP17", "ISHIKAWA JAPAN"),
+    ("JP18", "FUKUI JAPAN"),
+    ("JP19", "YAMANASHI JAPAN"),
+    ("JP20", "NAGANO JAPAN"),
+    ("JP21", "GIFU JAPAN"),
+    ("JP22", "SHIZUOKA JAPAN"),
+    ("JP23", "AICHI JAPAN"),
+    ("JP24", "MIE JAPAN"),
+    ("JP25", "SHIGA JAPAN"),
+    ("JP26", "KYOTO JAPAN"),
+    ("JP27", "OSAKA JAPAN"),
+    ("JP28", "HYOGO JAPAN"),
+    ("JP29", "NARA JAPAN"),
+    ("JP30", "WAKAYAMA JAPAN"),
+    ("JP31", "TOTTORI JAPAN"),
+    ("JP32", "SHIMANE JAPAN"),
+    ("JP33", "OKAYAMA JAPAN"),
+    ("JP34", "HIROSHIMA JAPAN"),
+    ("JP35", "YAMAGUCHI JAPAN"),
+    ("JP36", "TOKUSHIMA JAPAN"),
+    ("JP37", "KAGAWA JAPAN"),
+    ("JP38", "EHIME JAPAN"),
+    ("JP39", "KOCHI JAPAN"),
+    ("JP40", "FUKUOKA JAPAN"),
+    ("JP41", "SAGA JAPAN"),
+    ("JP42", "NAGASAKI JAPAN"),
+    ("JP43", "KUMAMOTO JAPAN"),
+    ("JP44", "OITA JAPAN"),
+    ("JP45", "MIYAZAKI JAPAN"),
+    ("JP46", "KAGOSHIMA JAPAN"),
+    ("JP47", "OKINAWA JAPAN"),
+];
+
+pub fn area_name(id: &str) -> Option<&'static str> {
+    AREAS.iter().find(|(x, _)| *x == id).map(|(_, name)| *name)
+}
+
+/// position in `AREAS`, for sorting by prefecture code
+pub fn area_index(id: &str) -> usize {
+    AREAS.iter().position(|(x, _)| *x == id).unwrap_or(AREAS.len())
+}
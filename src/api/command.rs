@@ -0,0 +1,111 @@
+use crate::errors::RadicoError::CommandError;
+use anyhow::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+use tokio::sync::oneshot;
+
+/// player actions, from keys or the control socket
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Command {
+    Next,
+    Prev,
+    /// station id or name
+    Tune(String),
+    /// 0-9
+    Volume(char),
+    Stop,
+    Play,
+    /// a preset, or the next one
+    Eq(Option<String>),
+    Menu,
+    /// pick an earlier program of today to play
+    Guide,
+    Info,
+    /// cycle the sleep timer, or resume when stopped
+    Sleep,
+    Snooze,
+    AlarmStop,
+    Loudness,
+}
+
+impl Command {
+    pub fn from_key(c: char) -> Option<Command> {
+        let cmd = match c {
+            '0'..='9' => Command::Volume(c),
+            'n' => Command::Next,
+            'p' => Command::Prev,
+            'm' => Command::Menu,
+            'g' => Command::Guide,
+            'i' => Command::Info,
+            'e' => Command::Eq(None),
+            's' => Command::Sleep,
+            'z' => Command::Snooze,
+            'x' => Command::AlarmStop,
+            'l' => Command::Loudness,
+            _ => return None,
+        };
+        Some(cmd)
+    }
+}
+
+impl FromStr for Command {
+    type Err = Error;
+
+    /// the control socket syntax: `next`, `prev`, `tune <station>`,
+    /// `volume <0-9>`, `stop`, `play`, `eq [preset]`
+    fn from_str(s: &str) -> Result<Self> {
+        let (cmd, arg) = match s.trim().split_once(' ') {
+            Some((cmd, arg)) => (cmd, Some(arg.trim())),
+            None => (s.trim(), None),
+        };
+        let err = || Error::from(CommandError(s.to_string()));
+        let cmd = match (cmd, arg) {
+            ("next", None) => Command::Next,
+            ("prev", None) => Command::Prev,
+            ("tune", Some(station)) => Command::Tune(station.to_string()),
+            ("volume", Some(level)) => match level.parse::<u8>() {
+                Ok(level @ 0..=9) => Command::Volume((b'0' + level) as char),
+                _ => return Err(err()),
+            },
+            ("stop", None) => Command::Stop,
+            ("play", None) => Command::Play,
+            ("eq", preset) => Command::Eq(preset.map(str::to_string)),
+            _ => return Err(err()),
+        };
+        Ok(cmd)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Next => write!(f, "next"),
+            Command::Prev => write!(f, "prev"),
+            Command::Tune(station) => write!(f, "tune {}", station),
+            Command::Volume(level) => write!(f, "volume {}", level),
+            Command::Stop => write!(f, "stop"),
+            Command::Play => write!(f, "play"),
+            Command::Eq(Some(preset)) => write!(f, "eq {}", preset),
+            Command::Eq(None) => write!(f, "eq"),
+            Command::Menu => write!(f, "menu"),
+            Command::Guide => write!(f, "guide"),
+            Command::Info => write!(f, "info"),
+            Command::Sleep => write!(f, "sleep"),
+            Command::Snooze => write!(f, "snooze"),
+            Command::AlarmStop => write!(f, "alarm stop"),
+            Command::Loudness => write!(f, "loudness"),
+        }
+    }
+}
+
+/// a command and, for remote ones, where to send the outcome
+pub struct Request {
+    pub cmd: Command,
+    pub reply: Option<oneshot::Sender<Result<String, String>>>,
+}
+
+impl From<Command> for Request {
+    fn from(cmd: Command) -> Self {
+        Request { cmd, reply: None }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::errors::RadicoError;
+use crate::errors::RadicoError::RetryExhausted;
+use anyhow::Error;
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// consecutive failures before the link is considered down
+const OFFLINE_AFTER: u32 = 3;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum Link {
+    #[default]
+    Online,
+    Degraded,
+    Offline,
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Link::Online => write!(f, "online"),
+            Link::Degraded => write!(f, "degraded"),
+            Link::Offline => write!(f, "offline"),
+        }
+    }
+}
+
+/// what was fetched; a playlist coming through while its segments do not
+/// is no sign of the link being back
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Fetch {
+    Playlist,
+    Segment,
+}
+
+pub struct Connectivity {
+    pub state: Link,
+    /// consecutive failures of each kind of fetch
+    playlist: u32,
+    segment: u32,
+    since: Instant,
+}
+
+impl Default for Connectivity {
+    fn default() -> Self {
+        Self {
+            state: Link::Online,
+            playlist: 0,
+            segment: 0,
+            since: Instant::now(),
+        }
+    }
+}
+
+impl Connectivity {
+    /// record a successful fetch, returns the previous state on change
+    pub fn success(&mut self, fetch: Fetch) -> Option<Link> {
+        *self.count(fetch) = 0;
+        self.update()
+    }
+
+    /// record a network failure, returns the previous state on change
+    pub fn failure(&mut self, fetch: Fetch) -> Option<Link> {
+        *self.count(fetch) += 1;
+        self.update()
+    }
+
+    /// wait before the next attempt
+    pub fn backoff(&self) -> Duration {
+        match self.state {
+            Link::Online => Duration::ZERO,
+            Link::Degraded => Duration::from_secs(self.failures() as u64),
+            Link::Offline => Duration::from_secs(
+                1 << (self.failures() - OFFLINE_AFTER).min(6),
+            )
+            .min(MAX_BACKOFF),
+        }
+    }
+
+    /// time spent in the current state
+    pub fn elapsed(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    fn count(&mut self, fetch: Fetch) -> &mut u32 {
+        match fetch {
+            Fetch::Playlist => &mut self.playlist,
+            Fetch::Segment => &mut self.segment,
+        }
+    }
+
+    /// the link is as bad as the worse of the two
+    fn failures(&self) -> u32 { self.playlist.max(self.segment) }
+
+    fn update(&mut self) -> Option<Link> {
+        match self.failures() {
+            0 => self.set(Link::Online),
+            n if n >= OFFLINE_AFTER => self.set(Link::Offline),
+            _ => self.set(Link::Degraded),
+        }
+    }
+
+    fn set(&mut self, state: Link) -> Option<Link> {
+        if self.state == state {
+            return None;
+        }
+        self.since = Instant::now();
+        Some(std::mem::replace(&mut self.state, state))
+    }
+}
+
+/// transport failure as opposed to a rejected request
+pub fn network_error(e: &Error) -> bool {
+    e.chain().any(|x| x.is::<reqwest::Error>())
+        || matches!(e.downcast_ref::<RadicoError>(), Some(RetryExhausted(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    /// how a stand-in for the stream server treats connections
+    #[derive(Clone, Copy)]
+    enum Serve {
+        Ok,
+        Drop,
+    }
+
+    /// a local server answering `n` connections, 200 or hanging up
+    fn stand_in(serve: Serve, n: usize) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(n) {
+                let mut stream = stream.unwrap();
+                if let Serve::Ok = serve {
+                    let mut buf = [0; 1024];
+                    let _ = stream.read(&mut buf);
+                    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+                }
+            }
+        });
+        addr
+    }
+
+    /// a port nothing listens on
+    fn refused() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    async fn fetch(link: &mut Connectivity, fetch: Fetch, addr: SocketAddr) -> Option<Link> {
+        let client = reqwest::Client::builder().pool_max_idle_per_host(0).build().unwrap();
+        match client.get(format!("http://{}/", addr)).send().await {
+            Ok(_) => link.success(fetch),
+            Err(e) => {
+                assert!(network_error(&Error::from(e)));
+                link.failure(fetch)
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn transitions() {
+        let mut link = Connectivity::default();
+        let ok = stand_in(Serve::Ok, 8);
+        let drop = stand_in(Serve::Drop, 8);
+
+        assert_eq!(fetch(&mut link, Fetch::Playlist, ok).await, None);
+        assert_eq!(link.backoff(), Duration::ZERO);
+        assert_eq!(fetch(&mut link, Fetch::Playlist, refused()).await, Some(Link::Online));
+        assert_eq!(link.state, Link::Degraded);
+        assert_eq!(fetch(&mut link, Fetch::Playlist, drop).await, None);
+        assert_eq!(fetch(&mut link, Fetch::Playlist, refused()).await, Some(Link::Degraded));
+        assert_eq!(link.state, Link::Offline);
+        assert_eq!(link.backoff(), Duration::from_secs(1));
+        assert_eq!(fetch(&mut link, Fetch::Playlist, drop).await, None);
+        assert_eq!(link.backoff(), Duration::from_secs(2));
+
+        assert_eq!(fetch(&mut link, Fetch::Playlist, ok).await, Some(Link::Offline));
+        assert_eq!(link.state, Link::Online);
+        assert_eq!(link.backoff(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn segments_failing() {
+        let mut link = Connectivity::default();
+        let ok = stand_in(Serve::Ok, 8);
+        let drop = stand_in(Serve::Drop, 8);
+
+        // the playlist keeps coming, the segments do not
+        for _ in 0..OFFLINE_AFTER {
+            fetch(&mut link, Fetch::Playlist, ok).await;
+            fetch(&mut link, Fetch::Segment, drop).await;
+        }
+        assert_eq!(link.state, Link::Offline);
+        assert_eq!(fetch(&mut link, Fetch::Playlist, ok).await, None);
+        assert_eq!(link.state, Link::Offline);
+
+        assert_eq!(fetch(&mut link, Fetch::Segment, ok).await, Some(Link::Offline));
+        assert_eq!(link.state, Link::Online);
+    }
+}
@@ -0,0 +1,421 @@
+use crate::api::hls::Segment;
+use crate::api::timefree::{parse, parse_time, program_at, segment_start, trim};
+use crate::api::xml::{Prog, Station};
+use crate::api::Api;
+use crate::errors::RadicoError::{Incomplete, PastEnd, SegmentError, TimeError, Truncated, UnknownStation};
+use crate::util::store;
+use crate::util::tags::{self, strip_id3, Tags};
+use anyhow::{Error, Result};
+use chrono::{NaiveDateTime, TimeDelta};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// segments fetched at once unless --jobs says otherwise
+pub const JOBS: usize = 4;
+/// the manifest is written after this many segments, and at the end
+const SAVE_EVERY: usize = 20;
+const MANIFEST: &str = "manifest.json";
+
+/// what has been fetched into the part directory, to resume from
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    station_id: String,
+    /// YYYYMMDDhhmmss
+    from: String,
+    to: String,
+    map: Option<String>,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Part {
+    url: String,
+    duration_ms: u64,
+    /// size and md5 of the fetched segment, once written
+    size: Option<u64>,
+    md5: Option<String>,
+}
+
+impl Manifest {
+    fn load(dir: &Path) -> Option<Self> {
+        serde_json::from_slice(&fs::read(dir.join(MANIFEST)).ok()?).ok()
+    }
+
+    /// the manifest left by an earlier run of the same download, if there is one
+    fn resume(dir: &Path, station_id: &str, from: &str, to: &str) -> Option<Self> {
+        Self::load(dir).filter(|x| x.station_id == station_id && x.from == from && x.to == to && !x.parts.is_empty())
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        store::write_atomic(&dir.join(MANIFEST), &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+fn part_path(dir: &Path, i: usize) -> PathBuf {
+    dir.join(format!("{:05}.aac", i))
+}
+
+/// a segment must hold ADTS frames
+fn verify(buf: &[u8]) -> Result<()> {
+    match strip_id3(buf) {
+        [0xff, b, ..] if b & 0xf0 == 0xf0 => Ok(()),
+        _ => Err(Error::from(SegmentError(format!("{} bytes without ADTS sync", buf.len())))),
+    }
+}
+
+/// a part written earlier is complete and unchanged
+fn verified(dir: &Path, i: usize, part: &mut Part) -> bool {
+    let Ok(buf) = fs::read(part_path(dir, i)) else {
+        return false;
+    };
+    let md5 = format!("{:x}", md5::compute(&buf));
+    match (&part.size, &part.md5) {
+        (Some(size), Some(x)) => *size == buf.len() as u64 && *x == md5,
+        // written before the manifest was saved
+        _ if verify(&buf).is_ok() => {
+            (part.size, part.md5) = (Some(buf.len() as u64), Some(md5));
+            true
+        },
+        _ => false,
+    }
+}
+
+/// every segment from `from` to `to` of a past program, which has to cover
+/// the whole of it
+async fn collect(
+    api: &mut Api,
+    station_id: &str,
+    prog: &Prog,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Result<(Vec<Segment>, Option<String>)> {
+    let bounds = (parse(&prog.ft)?, parse(&prog.to)?);
+    let (mut cursor, mut last, mut map) = (from, None, None);
+    let mut segments = Vec::new();
+    while cursor < to {
+        let playlist = api.timefree(station_id, prog, cursor).await?;
+        map = playlist.map.or(map);
+        let window = trim(playlist.segments, last.as_ref(), cursor, bounds);
+        if window.is_empty() {
+            break;
+        }
+        for segment in window {
+            let start = segment_start(&segment, bounds).unwrap_or(cursor);
+            if start >= to {
+                cursor = to;
+                break;
+            }
+            cursor = start + TimeDelta::from_std(segment.duration).unwrap_or_default();
+            last = Some(segment.url.to_owned());
+            segments.push(segment);
+        }
+    }
+
+    // the first segment may start before `from`, the last end after `to`
+    let longest = segments.iter().map(|x| x.duration).max().unwrap_or_default();
+    let got = TimeDelta::from_std(segments.iter().map(|x| x.duration).sum()).unwrap_or_default();
+    let want = to - from;
+    if got + TimeDelta::from_std(longest).unwrap_or_default() < want {
+        return Err(Error::from(Truncated(got.num_seconds(), want.num_seconds())));
+    }
+    Ok((segments, map))
+}
+
+/// progress on stderr, overwritten in place
+fn progress(done: usize, total: usize, bytes: u64) {
+    eprint!(
+        "\r{}/{} segments {:.1} MB {}%  ",
+        done,
+        total,
+        bytes as f64 / 1_000_000.0,
+        done * 100 / total.max(1)
+    );
+}
+
+/// a past program, or part of one, to save
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub station: Station,
+    pub prog: Prog,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub output: PathBuf,
+    /// segments fetched at once
+    pub jobs: usize,
+    /// embed the station logo
+    pub logo: bool,
+}
+
+/// `<station>_<YYYYMMDDhhmm>.aac`
+pub fn file_name(station_id: &str, from: NaiveDateTime) -> String {
+    format!("{}_{}.aac", station_id, from.format("%Y%m%d%H%M"))
+}
+
+/// `radico download`: fetch a past program into a single file, resuming
+/// from the part directory left by an interrupted run
+pub async fn run(
+    station: String,
+    from: String,
+    to: Option<String>,
+    output: Option<PathBuf>,
+    jobs: Option<usize>,
+    logo: bool,
+    url: String,
+) -> Result<()> {
+    let from = parse_time(&from)?;
+    let mut api = Api::new(url);
+    api.init().await?;
+    let station = api.lookup(&station).ok_or(UnknownStation(station))?;
+    let prog = program_at(&mut api, &station.id, from).await?;
+    let end = parse(&prog.to)?;
+    let to = match to {
+        Some(to) => parse_time(&to)?,
+        None => end,
+    };
+    if to <= from {
+        return Err(Error::from(TimeError(to.to_string())));
+    }
+    // one program at a time, its playlist ends with it
+    if to > end {
+        return Err(Error::from(PastEnd(to.to_string(), end.to_string())));
+    }
+
+    let output = output.unwrap_or_else(|| PathBuf::from(file_name(&station.id, from)));
+    let job = Job {
+        station,
+        prog,
+        from,
+        to,
+        output,
+        jobs: jobs.unwrap_or(JOBS),
+        logo,
+    };
+    fetch(&mut api, &job).await
+}
+
+/// save a job to its output, through a part directory next to it
+pub async fn fetch(api: &mut Api, job: &Job) -> Result<()> {
+    let Job {
+        station,
+        prog,
+        from,
+        to,
+        output,
+        jobs,
+        logo,
+    } = job;
+    let (from, to) = (*from, *to);
+    let mut dir = output.as_os_str().to_owned();
+    dir.push(".part");
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir)?;
+
+    let (ft, to_s) = (from.format("%Y%m%d%H%M%S").to_string(), to.format("%Y%m%d%H%M%S").to_string());
+    let mut manifest = match Manifest::resume(&dir, &station.id, &ft, &to_s) {
+        Some(x) => x,
+        None => {
+            let (segments, map) = collect(api, &station.id, prog, from, to).await?;
+            let parts = segments
+                .into_iter()
+                .map(|x| Part {
+                    url: x.url,
+                    duration_ms: x.duration.as_millis() as u64,
+                    ..Default::default()
+                })
+                .collect();
+            let manifest = Manifest {
+                station_id: station.id.to_owned(),
+                from: ft,
+                to: to_s,
+                map,
+                parts,
+            };
+            manifest.save(&dir)?;
+            manifest
+        },
+    };
+
+    let total = manifest.parts.len();
+    let pending = (0..total)
+        .filter(|&i| !verified(&dir, i, &mut manifest.parts[i]))
+        .collect::<Vec<_>>();
+    eprintln!(
+        "{} {} - {} {}: {} segments, {} to fetch",
+        station.name,
+        from.format("%Y-%m-%d %H:%M"),
+        to.format("%H:%M"),
+        prog.title,
+        total,
+        pending.len()
+    );
+
+    let mut bytes = manifest.parts.iter().filter_map(|x| x.size).sum::<u64>();
+    let mut done = total - pending.len();
+    let mut failed = 0;
+    progress(done, total, bytes);
+
+    let jobs = Arc::new(Semaphore::new((*jobs).max(1)));
+    let mut set = JoinSet::new();
+    let mut finish = |res: Result<(usize, Vec<u8>)>, manifest: &mut Manifest| {
+        match res {
+            Ok((i, buf)) => {
+                let part = &mut manifest.parts[i];
+                (part.size, part.md5) = (Some(buf.len() as u64), Some(format!("{:x}", md5::compute(&buf))));
+                bytes += buf.len() as u64;
+                done += 1;
+                if done % SAVE_EVERY == 0 {
+                    if let Err(e) = manifest.save(&dir) {
+                        error!("manifest: {:?}", e);
+                    }
+                }
+            },
+            Err(e) => {
+                error!("download: {:?}", e);
+                failed += 1;
+            },
+        }
+        progress(done, total, bytes);
+    };
+
+    for i in pending {
+        let permit = Arc::clone(&jobs).acquire_owned().await?;
+        let mut api = api.clone();
+        let url = manifest.parts[i].url.to_owned();
+        let map = manifest.map.to_owned();
+        let path = part_path(&dir, i);
+        set.spawn(async move {
+            let _permit = permit;
+            let buf = api.get_segment(&url, map.as_deref()).await?;
+            verify(&buf)?;
+            store::write_atomic(&path, &buf)?;
+            Ok((i, buf))
+        });
+        while let Some(res) = set.try_join_next() {
+            finish(res?, &mut manifest);
+        }
+    }
+    while let Some(res) = set.join_next().await {
+        finish(res?, &mut manifest);
+    }
+    eprintln!();
+    manifest.save(&dir)?;
+    if failed > 0 {
+        return Err(Error::from(Incomplete(failed)));
+    }
+
+    // a cropped download is tagged with what it holds
+    let mut tags = Tags::new(&station.name, prog);
+    (tags.start, tags.end) = (Some(from), Some(to));
+    if *logo {
+        match api.logo(station).await {
+            Ok(x) => tags.logo = Some(x),
+            // the recording is still worth having without it
+            Err(e) => error!("logo: {:?}", e),
+        }
+    }
+
+    // concatenate, checking each part against the manifest once more
+    let mut tmp = output.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = BufWriter::new(fs::File::create(&tmp)?);
+    for i in 0..total {
+        let buf = fs::read(part_path(&dir, i))?;
+        let part = &manifest.parts[i];
+        if part.md5.as_deref() != Some(&format!("{:x}", md5::compute(&buf))) {
+            return Err(Error::from(SegmentError(format!("{} changed on disk", i))));
+        }
+        file.write_all(strip_id3(&buf))?;
+    }
+    file.into_inner()?.sync_all()?;
+    tags::write(Path::new(&tmp), &tags)?;
+    fs::rename(&tmp, output)?;
+    fs::remove_dir_all(&dir)?;
+    info!("download {:?}\r", output);
+    eprintln!("{}", output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADTS: [u8; 4] = [0xff, 0xf1, 0x50, 0x80];
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("radico-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn segments() {
+        // radiko puts a timed ID3 tag in front of every segment, sometimes more
+        let tagged = [tags::id3v2(&Tags::default()), tags::id3v2(&Tags::default()), ADTS.to_vec()].concat();
+        assert!(verify(&tagged).is_ok());
+        assert!(verify(&ADTS).is_ok());
+        // a tag cut short leaves nothing to play
+        assert!(verify(&tagged[..15]).is_err());
+        // an error page, or anything else
+        assert!(verify(b"<html>").is_err());
+        assert!(verify(&[0xff, 0x00, 0x50, 0x80]).is_err());
+        assert!(verify(&[]).is_err());
+    }
+
+    #[test]
+    fn parts() {
+        let dir = temp_dir("parts");
+        let mut part = Part::default();
+        assert!(!verified(&dir, 0, &mut part));
+
+        // written before the manifest was saved, checked and then remembered
+        fs::write(part_path(&dir, 0), ADTS).unwrap();
+        assert!(verified(&dir, 0, &mut part));
+        assert_eq!(part.size, Some(4));
+        assert_eq!(part.md5, Some(format!("{:x}", md5::compute(ADTS))));
+        assert!(verified(&dir, 0, &mut part));
+
+        // changed since
+        fs::write(part_path(&dir, 0), [ADTS, ADTS].concat()).unwrap();
+        assert!(!verified(&dir, 0, &mut part));
+        fs::write(part_path(&dir, 0), [0xff, 0xf1, 0x50, 0x81]).unwrap();
+        assert!(!verified(&dir, 0, &mut part));
+
+        fs::write(part_path(&dir, 1), b"<html>").unwrap();
+        assert!(!verified(&dir, 1, &mut Part::default()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resume() {
+        let dir = temp_dir("resume");
+        assert!(Manifest::resume(&dir, "TBS", "20261017060000", "20261017083000").is_none());
+
+        let mut manifest = Manifest {
+            station_id: "TBS".to_string(),
+            from: "20261017060000".to_string(),
+            to: "20261017083000".to_string(),
+            ..Default::default()
+        };
+        // nothing was collected
+        manifest.save(&dir).unwrap();
+        assert!(Manifest::resume(&dir, "TBS", "20261017060000", "20261017083000").is_none());
+
+        manifest.parts.push(Part::default());
+        manifest.save(&dir).unwrap();
+        assert!(Manifest::resume(&dir, "TBS", "20261017060000", "20261017083000").is_some());
+        // another download into the same output collects again
+        assert!(Manifest::resume(&dir, "QRR", "20261017060000", "20261017083000").is_none());
+        assert!(Manifest::resume(&dir, "TBS", "20261017070000", "20261017083000").is_none());
+        assert!(Manifest::resume(&dir, "TBS", "20261017060000", "20261017090000").is_none());
+
+        fs::write(dir.join(MANIFEST), b"{").unwrap();
+        assert!(Manifest::resume(&dir, "TBS", "20261017060000", "20261017083000").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
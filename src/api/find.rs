@@ -0,0 +1,181 @@
+use crate::api::download;
+use crate::api::guide::{self, broadcast_date, broadcast_date_of, jst_now};
+use crate::api::record::{self, Recording};
+use crate::api::timefree::parse;
+use crate::api::xml::{Prog, Station};
+use crate::api::{strip_html, Api};
+use crate::errors::RadicoError::{EmptyQuery, NoMatch, UnknownStation};
+use crate::terminal::args::Format;
+use crate::terminal::table;
+use crate::util::search::normalize;
+use anyhow::{Error, Result};
+use chrono::Days;
+use log::error;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// the guide reaches a week ahead
+const DAYS: u64 = 7;
+/// weight of a query word found in the title, performers or description
+const TITLE: i64 = 3;
+const PERFORMER: i64 = 2;
+const INFO: i64 = 1;
+
+/// how well every word of the query is found in a program, none if one is
+/// missing
+fn score(words: &[String], prog: &Prog) -> Option<i64> {
+    let fields = [
+        (normalize(&prog.title), TITLE),
+        (normalize(&prog.pfm), PERFORMER),
+        (normalize(&format!("{} {}", strip_html(&prog.info), strip_html(&prog.desc))), INFO),
+    ];
+    words
+        .iter()
+        .map(|w| {
+            fields
+                .iter()
+                .filter(|(field, _)| field.contains(w.as_str()))
+                .map(|(_, weight)| *weight)
+                .max()
+        })
+        .sum()
+}
+
+/// a program as `<station id>@<YYYYMMDDhhmmss>`, the same from one search to
+/// the next
+fn key(station_id: &str, prog: &Prog) -> String { format!("{}@{}", station_id, prog.ft) }
+
+/// schedule the program of a key printed by an earlier search
+async fn schedule(api: &mut Api, key: &str) -> Result<()> {
+    let (id, ft) = key.split_once('@').ok_or(NoMatch(key.to_string()))?;
+    let station = api
+        .lookup(id)
+        .filter(|x| x.id == id)
+        .ok_or(UnknownStation(id.to_string()))?;
+    let prog = guide::day(api, id, broadcast_date_of(parse(ft)?))
+        .await?
+        .into_iter()
+        .find(|x| x.ft == ft)
+        .ok_or(NoMatch(key.to_string()))?;
+    let added = record::schedule(Recording::new(&station, &prog))?;
+    eprintln!(
+        "{} {} {}: {}",
+        station.name,
+        parse(&prog.ft)?.format("%m/%d %H:%M"),
+        prog.title,
+        if added { "scheduled" } else { "already scheduled" }
+    );
+    Ok(())
+}
+
+/// `radico search`: programs of the area, or of one station, from now to
+/// `days` ahead matching a keyword or performer; `record` schedules one by
+/// the key the listing shows
+pub async fn run(
+    query: String,
+    days: Option<u64>,
+    station: Option<String>,
+    area: Option<String>,
+    record: Option<String>,
+    format: Format,
+    url: String,
+) -> Result<()> {
+    let words = query
+        .split_whitespace()
+        .map(normalize)
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    // nothing to look for would match every program
+    if words.is_empty() && record.is_none() {
+        return Err(Error::from(EmptyQuery(query)));
+    }
+
+    let mut api = Api::new(url);
+    api.init().await?;
+    if let Some(key) = record {
+        return schedule(&mut api, &key).await;
+    }
+    let stations = match &station {
+        Some(key) => vec![api.lookup(key).ok_or(UnknownStation(key.to_owned()))?],
+        None => {
+            let area = area.or(api.detected_area().map(str::to_string)).unwrap_or_default();
+            api.stations_in(&area)?
+        },
+    };
+
+    let now = jst_now();
+    let today = broadcast_date();
+
+    // every day of every station, a few at a time
+    let jobs = Arc::new(Semaphore::new(download::JOBS));
+    let mut set = JoinSet::new();
+    for station in &stations {
+        for day in 0..days.unwrap_or(DAYS) {
+            let Some(date) = today.checked_add_days(Days::new(day)) else { break };
+            let permit = Arc::clone(&jobs).acquire_owned().await?;
+            let mut api = api.clone();
+            let station = station.to_owned();
+            set.spawn(async move {
+                let _permit = permit;
+                let progs = guide::day(&mut api, &station.id, date).await;
+                (station, date, progs)
+            });
+        }
+    }
+
+    let mut found: Vec<(i64, Station, Prog)> = Vec::new();
+    while let Some(res) = set.join_next().await {
+        let (station, date, progs) = res?;
+        let progs = match progs {
+            Ok(x) => x,
+            Err(e) => {
+                // the guide of a day not published yet, or a station out of
+                // reach
+                error!("search {} {}: {:?}", station.id, date, e);
+                continue;
+            },
+        };
+        for prog in progs {
+            if parse(&prog.to).is_ok_and(|x| x <= now) {
+                continue;
+            }
+            if let Some(score) = score(&words, &prog) {
+                found.push((score, station.to_owned(), prog));
+            }
+        }
+    }
+    // by time, the better match first at the same time
+    found.sort_by(|a, b| a.2.ft.cmp(&b.2.ft).then(b.0.cmp(&a.0)));
+
+    let time = |x: &str, f: &str| match format {
+        // machine readable formats keep YYYYMMDDhhmmss
+        Format::Table => parse(x).map(|x| x.format(f).to_string()).unwrap_or_else(|_| x.to_string()),
+        _ => x.to_string(),
+    };
+    let rows = found
+        .iter()
+        .map(|(_, station, prog)| {
+            let mut row = vec![
+                key(&station.id, prog),
+                time(&prog.ft, "%m/%d %a %H:%M"),
+                time(&prog.to, "%H:%M"),
+                station.id.to_owned(),
+                prog.title.to_owned(),
+                prog.pfm.to_owned(),
+            ];
+            if format != Format::Table {
+                row.push(station.name.to_owned());
+                row.push(prog.url.to_owned());
+            }
+            row
+        })
+        .collect::<Vec<_>>();
+    let header = ["key", "ft", "to", "station_id", "title", "pfm", "station", "url"];
+    let header = match format {
+        Format::Table => &header[..6],
+        _ => &header[..],
+    };
+    table::print(format, header, &rows);
+    Ok(())
+}
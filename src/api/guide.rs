@@ -0,0 +1,200 @@
+use crate::api::timefree::parse;
+use crate::api::xml::Prog;
+use crate::api::Api;
+use crate::util::store;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{LazyLock, Mutex, Once};
+use std::time::Duration;
+
+/// radiko keeps Japan time whatever the local zone is
+const JST: i32 = 9 * 60 * 60;
+/// the broadcast day runs from 5:00 to 29:00
+const DAY_START: TimeDelta = TimeDelta::hours(5);
+/// how long a day still to come is trusted before it is fetched again
+const TTL: Duration = Duration::from_secs(60 * 60);
+/// days kept after they are over, as long as past programs can be played
+const KEEP: u64 = 8;
+const DIR: &str = "guide";
+
+/// a broadcast day of a station as fetched
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Day {
+    /// unix time of the fetch
+    fetched: i64,
+    progs: Vec<Prog>,
+}
+
+impl Day {
+    /// a day over stays as it is, the others are fetched again after a while
+    fn stale(&self, date: NaiveDate) -> bool {
+        date >= broadcast_date() && Utc::now().timestamp() - self.fetched >= TTL.as_secs() as i64
+    }
+}
+
+type Key = (String, NaiveDate);
+
+/// days fetched in this process, shared by the player, search and recording
+static CACHE: LazyLock<Mutex<HashMap<Key, Day>>> = LazyLock::new(Default::default);
+/// days being fetched in the background
+static REFRESHING: LazyLock<Mutex<HashSet<Key>>> = LazyLock::new(Default::default);
+static PRUNE: Once = Once::new();
+
+/// the time in Japan, which program times are in
+pub fn jst_now() -> NaiveDateTime { jst(Utc::now()) }
+
+fn jst(at: DateTime<Utc>) -> NaiveDateTime {
+    at.with_timezone(&FixedOffset::east_opt(JST).unwrap())
+        .naive_local()
+}
+
+/// the broadcast day a time belongs to, 4:59 being the end of the day before
+pub fn broadcast_date_of(at: NaiveDateTime) -> NaiveDate {
+    (at - DAY_START).date()
+}
+
+/// today's broadcast day in Japan
+pub fn broadcast_date() -> NaiveDate {
+    broadcast_date_of(jst_now())
+}
+
+fn file((station_id, date): &Key) -> String {
+    format!("{}/{}_{}.json", DIR, station_id, date.format("%Y%m%d"))
+}
+
+/// drop the days over for longer than past programs last
+fn prune() {
+    let Some(oldest) = broadcast_date().checked_sub_days(chrono::Days::new(KEEP)) else {
+        return;
+    };
+    CACHE.lock().unwrap().retain(|(_, date), _| *date >= oldest);
+    let Some(dir) = store::dir().map(|x| x.join(DIR)) else {
+        return;
+    };
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let date = name
+            .trim_end_matches(".json")
+            .rsplit_once('_')
+            .and_then(|(_, x)| NaiveDate::parse_from_str(x, "%Y%m%d").ok());
+        if date.is_some_and(|x| x < oldest) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+fn cached(key: &Key) -> Option<Day> {
+    if let Some(day) = CACHE.lock().unwrap().get(key) {
+        return Some(day.to_owned());
+    }
+    let day: Day = store::load(&file(key));
+    if day.progs.is_empty() {
+        return None;
+    }
+    CACHE.lock().unwrap().insert(key.to_owned(), day.to_owned());
+    Some(day)
+}
+
+async fn fetch(api: &mut Api, key: &Key) -> Result<Vec<Prog>> {
+    let progs = api.guide(&key.0, key.1).await?;
+    let day = Day {
+        fetched: Utc::now().timestamp(),
+        progs,
+    };
+    PRUNE.call_once(prune);
+    if let Err(e) = store::save(&file(key), &day) {
+        info!("{}: {:?}\r", file(key), e);
+    }
+    CACHE.lock().unwrap().insert(key.to_owned(), day.to_owned());
+    Ok(day.progs)
+}
+
+/// fetch a day in the background, once at a time
+fn refresh(api: &Api, key: Key) {
+    if !REFRESHING.lock().unwrap().insert(key.to_owned()) {
+        return;
+    }
+    let mut api = api.clone();
+    tokio::spawn(async move {
+        if let Err(e) = fetch(&mut api, &key).await {
+            info!("guide {:?}: {:?}\r", key, e);
+        }
+        REFRESHING.lock().unwrap().remove(&key);
+    });
+}
+
+/// the programs of a station on a broadcast day; a stale day is served as
+/// it is while a fresh one is fetched behind
+pub async fn day(api: &mut Api, station_id: &str, date: NaiveDate) -> Result<Vec<Prog>> {
+    let key = (station_id.to_string(), date);
+    match cached(&key) {
+        Some(day) if day.stale(date) => {
+            refresh(api, key);
+            Ok(day.progs)
+        },
+        Some(day) => Ok(day.progs),
+        None => fetch(api, &key).await,
+    }
+}
+
+/// fetch the next broadcast day ahead of 5:00, so the program change then
+/// does not wait on it
+pub fn prefetch(api: &Api, station_id: &str) {
+    let Some(date) = broadcast_date().succ_opt() else {
+        return;
+    };
+    let key = (station_id.to_string(), date);
+    if cached(&key).is_none_or(|x| x.stale(date)) {
+        refresh(api, key);
+    }
+}
+
+/// the program on air at a time; one running across 5:00 may only be listed
+/// on the day before
+pub async fn on_air(api: &mut Api, station_id: &str, at: NaiveDateTime) -> Result<Option<Prog>> {
+    let covers = |x: &Prog| parse(&x.ft).is_ok_and(|ft| ft <= at) && parse(&x.to).is_ok_and(|to| at < to);
+    let date = broadcast_date_of(at);
+    if let Some(prog) = day(api, station_id, date).await?.into_iter().find(covers) {
+        return Ok(Some(prog));
+    }
+    let Some(before) = date.pred_opt() else {
+        return Ok(None);
+    };
+    let progs = day(api, station_id, before).await.unwrap_or_default();
+    Ok(progs.into_iter().find(covers))
+}
+
+/// the last program started by a time, for a gap in the guide or a program
+/// overrunning its listed end
+pub async fn last_started(api: &mut Api, station_id: &str, at: NaiveDateTime) -> Result<Option<Prog>> {
+    let progs = day(api, station_id, broadcast_date_of(at)).await?;
+    Ok(progs.into_iter().rev().find(|x| parse(&x.ft).is_ok_and(|ft| ft <= at)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> { s.parse().unwrap() }
+
+    #[test]
+    fn day_start() {
+        let date = |x| broadcast_date_of(NaiveDateTime::parse_from_str(x, "%Y-%m-%d %H:%M:%S").unwrap());
+        assert_eq!(date("2026-10-17 04:59:59"), NaiveDate::from_ymd_opt(2026, 10, 16).unwrap());
+        assert_eq!(date("2026-10-17 05:00:00"), NaiveDate::from_ymd_opt(2026, 10, 17).unwrap());
+        assert_eq!(date("2026-10-17 23:59:59"), NaiveDate::from_ymd_opt(2026, 10, 17).unwrap());
+        // past midnight, still 24:00 to 28:59 of the day before
+        assert_eq!(date("2026-10-18 00:30:00"), NaiveDate::from_ymd_opt(2026, 10, 17).unwrap());
+    }
+
+    #[test]
+    fn japan_time() {
+        // 4:59 and 5:00 in Japan are 19:59 and 20:00 UTC the day before
+        assert_eq!(broadcast_date_of(jst(utc("2026-10-16T19:59:00Z"))), NaiveDate::from_ymd_opt(2026, 10, 16).unwrap());
+        assert_eq!(broadcast_date_of(jst(utc("2026-10-16T20:00:00Z"))), NaiveDate::from_ymd_opt(2026, 10, 17).unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::errors::RadicoError::PlaylistError;
+use anyhow::{Error, Result};
+use reqwest::Url;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// HLS media playlist
+#[derive(Debug, Default, Clone)]
+pub struct MediaPlaylist {
+    pub target_duration: Duration,
+    pub segments: Vec<Segment>,
+    /// init section of fragmented MP4 segments
+    pub map: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Segment {
+    pub url: String,
+    pub duration: Duration,
+}
+
+impl MediaPlaylist {
+    /// make the segment and init section URIs absolute, relative to the
+    /// playlist at `base`
+    pub fn resolve(mut self, base: &Url) -> Result<Self> {
+        for segment in &mut self.segments {
+            segment.url = base.join(&segment.url)?.to_string();
+        }
+        if let Some(map) = self.map {
+            self.map = Some(base.join(&map)?.to_string());
+        }
+        Ok(self)
+    }
+}
+
+impl FromStr for MediaPlaylist {
+    type Err = Error;
+
+    fn from_str(body: &str) -> Result<Self> {
+        let mut playlist = MediaPlaylist::default();
+        let mut duration = None;
+
+        for line in body.lines().map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            if let Some(v) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+                playlist.target_duration = Duration::from_secs(v.parse()?);
+            } else if let Some(v) = line.strip_prefix("#EXTINF:") {
+                let v = v.split(',').next().unwrap_or_default();
+                duration = Some(Duration::from_secs_f64(v.parse()?));
+            } else if let Some(v) = line.strip_prefix("#EXT-X-MAP:") {
+                playlist.map = v
+                    .split(',')
+                    .find_map(|x| x.strip_prefix("URI="))
+                    .map(|x| x.trim_matches('"').to_string());
+            } else if !line.starts_with('#') {
+                playlist.segments.push(Segment {
+                    url: line.to_string(),
+                    duration: duration.take().unwrap_or(playlist.target_duration),
+                });
+            }
+        }
+
+        if playlist.target_duration.is_zero() {
+            // fall back on the longest segment
+            playlist.target_duration = playlist
+                .segments
+                .iter()
+                .map(|x| x.duration)
+                .max()
+                .ok_or(PlaylistError)?
+                .max(Duration::from_secs(1));
+        }
+        Ok(playlist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_playlist() {
+        let playlist: MediaPlaylist = include_str!("../../tests/fixtures/medialist.m3u8").parse().unwrap();
+        assert_eq!(playlist.target_duration, Duration::from_secs(5));
+        assert_eq!(playlist.map.as_deref(), Some("https://media.example.jp/tbs/init.mp4"));
+        let segments = playlist.segments.iter().map(|x| (x.url.as_str(), x.duration)).collect::<Vec<_>>();
+        assert_eq!(
+            segments,
+            [
+                ("https://media.example.jp/tbs/20261017210000_1.m4s", Duration::from_secs(5)),
+                ("https://media.example.jp/tbs/20261017210005_2.m4s", Duration::from_secs(5)),
+                ("https://media.example.jp/tbs/20261017210010_3.m4s", Duration::from_millis(4992)),
+            ]
+        );
+    }
+
+    #[test]
+    fn without_tags() {
+        // no target duration: the longest segment
+        let body = "#EXTM3U\n#EXTINF:3.5,\nhttps://a/1.aac\n#EXTINF:6.2,title\nhttps://a/2.aac\n";
+        let playlist: MediaPlaylist = body.parse().unwrap();
+        assert_eq!(playlist.target_duration, Duration::from_millis(6200));
+        assert_eq!(playlist.segments[0].duration, Duration::from_millis(3500));
+        assert!(playlist.map.is_none());
+        // segments without EXTINF last the target duration
+        let body = "#EXT-X-TARGETDURATION:5\nhttps://a/1.aac\n";
+        let playlist: MediaPlaylist = body.parse().unwrap();
+        assert_eq!(playlist.segments[0].duration, Duration::from_secs(5));
+        // never under a second
+        let playlist: MediaPlaylist = "#EXTINF:0.2,\nhttps://a/1.aac".parse().unwrap();
+        assert_eq!(playlist.target_duration, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn uri_lines() {
+        // any line that is not a tag is a segment, relative ones included
+        let body = "#EXT-X-TARGETDURATION:5\n#EXT-X-PROGRAM-DATE-TIME:2026-10-17T21:00:00+09:00\n#EXTINF:5,\n20261017210000_1.aac\n";
+        let playlist: MediaPlaylist = body.parse().unwrap();
+        assert_eq!(playlist.segments.len(), 1);
+        assert_eq!(playlist.segments[0].url, "20261017210000_1.aac");
+
+        let base = Url::parse("https://media.example.jp/tbs/chunklist.m3u8").unwrap();
+        let playlist = playlist.resolve(&base).unwrap();
+        assert_eq!(playlist.segments[0].url, "https://media.example.jp/tbs/20261017210000_1.aac");
+        // absolute ones stay as they are
+        let playlist: MediaPlaylist = include_str!("../../tests/fixtures/medialist.m3u8").parse().unwrap();
+        let resolved = playlist.clone().resolve(&base).unwrap();
+        assert_eq!(resolved.map, playlist.map);
+        assert_eq!(resolved.segments[2].url, playlist.segments[2].url);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!("#EXTM3U\n".parse::<MediaPlaylist>().is_err());
+        assert!("#EXT-X-TARGETDURATION:five\nhttps://a/1.aac".parse::<MediaPlaylist>().is_err());
+        assert!("#EXTINF:abc,\nhttps://a/1.aac".parse::<MediaPlaylist>().is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::api::area::{area_index, area_name};
+use crate::api::xml::Station;
+use crate::api::guide::{self, broadcast_date};
+use crate::api::{strip_html, Api};
+use crate::errors::RadicoError::{DateError, UnknownStation};
+use crate::terminal::args::{Cmd, Format};
+use crate::terminal::table;
+use anyhow::{Error, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use itertools::Itertools;
+
+/// run a listing command, which needs the station data but no audio device
+pub async fn run(cmd: Cmd) -> Result<()> {
+    match cmd {
+        Cmd::Stations { area, format, url } => stations(&mut scrape(url).await?, area, format),
+        Cmd::Areas { format, url } => areas(&mut scrape(url).await?, format),
+        Cmd::Guide {
+            date,
+            format,
+            station,
+            url,
+        } => guide(&mut scrape(url).await?, &station, date, format).await,
+        _ => Ok(()),
+    }
+}
+
+async fn scrape(url: String) -> Result<Api> {
+    let mut api = Api::new(url);
+    api.initializer().await?;
+    Ok(api)
+}
+
+/// every station with the region it is listed under
+fn all_stations(api: &Api) -> Vec<(&Station, &str, &str)> {
+    api.data
+        .region
+        .stations
+        .iter()
+        .flat_map(|x| {
+            x.station
+                .iter()
+                .map(|s| (s, x.region_id.as_str(), x.region_name.as_str()))
+        })
+        .collect()
+}
+
+fn stations(api: &mut Api, area: Option<String>, format: Format) -> Result<()> {
+    let rows = all_stations(api)
+        .into_iter()
+        .filter(|(s, _, _)| area.as_ref().is_none_or(|x| s.area_id.eq_ignore_ascii_case(x)))
+        .map(|(s, region_id, region_name)| {
+            vec![
+                s.id.to_owned(),
+                s.name.to_owned(),
+                s.area_id.to_owned(),
+                region_id.to_string(),
+                region_name.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table::print(
+        format,
+        &["id", "name", "area_id", "region_id", "region_name"],
+        &rows,
+    );
+    Ok(())
+}
+
+fn areas(api: &mut Api, format: Format) -> Result<()> {
+    let rows = all_stations(api)
+        .into_iter()
+        .into_group_map_by(|(s, _, _)| s.area_id.to_owned())
+        .into_iter()
+        .sorted_by_key(|(id, _)| area_index(id))
+        .map(|(id, v)| {
+            let (_, region_id, region_name) = v[0];
+            vec![
+                id.to_owned(),
+                area_name(&id).unwrap_or_default().to_string(),
+                region_id.to_string(),
+                region_name.to_string(),
+                v.len().to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table::print(
+        format,
+        &["area_id", "area_name", "region_id", "region_name", "stations"],
+        &rows,
+    );
+    Ok(())
+}
+
+async fn guide(api: &mut Api, station: &str, date: Option<String>, format: Format) -> Result<()> {
+    let id = all_stations(api)
+        .into_iter()
+        .find(|(s, _, _)| s.id.eq_ignore_ascii_case(station) || s.name == station)
+        .map(|(s, _, _)| s.id.to_owned())
+        .ok_or(UnknownStation(station.to_string()))?;
+    let date = match date {
+        None => broadcast_date(),
+        Some(d) => NaiveDate::parse_from_str(&d, "%Y%m%d")
+            .or_else(|_| NaiveDate::parse_from_str(&d, "%Y-%m-%d"))
+            .map_err(|_| Error::from(DateError(d)))?,
+    };
+
+    let progs = guide::day(api, &id, date).await?;
+    let time = |x: &str| match format {
+        // machine readable formats keep YYYYMMDDhhmmss
+        Format::Table => NaiveDateTime::parse_from_str(x, "%Y%m%d%H%M%S")
+            .map(|x| x.format("%H:%M").to_string())
+            .unwrap_or_else(|_| x.to_string()),
+        _ => x.to_string(),
+    };
+    let rows = progs
+        .iter()
+        .map(|x| {
+            let mut row = vec![time(&x.ft), time(&x.to), x.title.to_owned(), x.pfm.to_owned()];
+            if format != Format::Table {
+                row.push(strip_html(&x.info).split_whitespace().join(" "));
+                row.push(x.url.to_owned());
+                row.push(x.genres().join("/"));
+                row.push(x.tags().join("/"));
+            }
+            row
+        })
+        .collect::<Vec<_>>();
+    let header = ["ft", "to", "title", "pfm", "info", "url", "genre", "tag"];
+    let header = match format {
+        // the rest does not fit a line
+        Format::Table => &header[..4],
+        _ => &header[..],
+    };
+    table::print(format, header, &rows);
+    Ok(())
+}
@@ -1,14 +1,20 @@
-use crate::api::xml::{CurrentProg, PlaylistUrl, Region, Station};
+use crate::api::area::area_name;
+use crate::api::guide::jst_now;
+use crate::api::hls::MediaPlaylist;
+use crate::api::retry::RetryPolicy;
+use crate::api::xml::{CurrentProg, PlaylistUrl, Prog, Region, Station};
 use crate::audio::sink;
+use crate::errors::RadicoError;
 use crate::errors::RadicoError::*;
 use crate::terminal::args::{usage, Options};
+use crate::util::hooks::{self, Kind};
 use crate::util::menu::render_config;
+use crate::util::search;
 use crate::{lazy_regex, terminal};
 use anyhow::{Context, Error, Result};
-use async_recursion::async_recursion;
 use base64::engine::general_purpose;
 use base64::Engine;
-use chrono::{Local, NaiveDateTime};
+use chrono::{NaiveDate, NaiveDateTime};
 use http::{
     header::{InvalidHeaderName, InvalidHeaderValue},
     HeaderName,
@@ -35,9 +41,28 @@ use tokio::time::Instant;
 use unicode_normalization::UnicodeNormalization;
 use log::{error, info, warn};
 
+pub mod area;
+pub mod command;
+pub mod connectivity;
+pub mod download;
+pub mod find;
+pub mod guide;
+pub mod hls;
+pub mod listing;
+pub mod record;
+pub mod retry;
+pub mod timefree;
 pub mod worker;
 pub mod xml;
 
+/// catch-up playlist, by time of a past program
+const TIMEFREE_PATH: &str = "/v2/api/ts/playlist.m3u8";
+/// station logo, by station id
+const LOGO_PATH: &str = "/v2/static/station/logo";
+
+/// assumed lifetime of an auth token
+pub const TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+
 pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/69.0.3497.100";
 
 #[derive(Clone)]
@@ -47,6 +72,7 @@ pub struct Api {
     pub param: Param,
     pub data: Data,
     pub current: State,
+    pub retry: RetryPolicy,
     pub f1: Arc<AtomicBool>,
 }
 
@@ -82,6 +108,14 @@ pub struct State {
     area_name: Option<String>,
     plist_url: Option<PlaylistUrl>,
     to: NaiveDateTime,
+    authed: Option<Instant>,
+    init: Option<(String, Vec<u8>)>,
+    /// program on air, to tell when it changes
+    prog: Option<Prog>,
+    /// area detected at startup
+    home: Option<String>,
+    /// auth headers of the catch-up playlist requests
+    timefree: Option<HeaderMap>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -98,6 +132,19 @@ impl PartialEq<Station> for &Station {
 
 impl Default for Api {
     fn default() -> Self {
+        let domain = match Options::init().url {
+            None => {
+                println!("{}", usage());
+                terminal::quit(Error::from(Quit));
+            },
+            Some(d) => d,
+        };
+        Api::new(domain)
+    }
+}
+
+impl Api {
+    pub fn new(domain: String) -> Self {
         let mut headers = HeaderMap::new();
         headers.insert("Accept", HeaderValue::from_static("*/*"));
         let arg = Options::init();
@@ -156,13 +203,6 @@ impl Default for Api {
             .build()
             .unwrap();
 
-        let domain = match arg.url {
-            None => {
-                println!("{}", usage());
-                terminal::quit(Error::from(Quit));
-            },
-            Some(d) => d,
-        };
         Api {
             client,
             url: Url {
@@ -174,12 +214,11 @@ impl Default for Api {
                 region: Default::default(),
             },
             current: Default::default(),
+            retry: Default::default(),
             f1: Arc::new(Default::default()),
         }
     }
-}
 
-impl Api {
     pub async fn init(&mut self) -> Result<()> {
         self.initializer().await.expect("Initialize Error");
         self.login_check().await.expect("login check error");
@@ -362,6 +401,7 @@ impl Api {
         self.station_url().await.expect("failed to get station url");
         self.current_prog().await?;
         info!(">>>>> set {:?}\r", station);
+        hooks::emit(self.event(Kind::StationChange));
         Ok(())
     }
 
@@ -371,41 +411,63 @@ impl Api {
         let area_id = self.current.area_id.as_ref().unwrap();
         let area_name = self.current.area_name.as_ref().unwrap();
         println!("{} ({})\r", area_name, area_id);
-        let stations = self
-            .to_owned()
-            .data
-            .region
-            .stations
-            .into_iter()
-            .flat_map(|x| x.station.into_iter().collect::<Vec<_>>())
-            .collect::<Vec<_>>();
+        self.current.home = self.current.area_id.to_owned();
 
-        self.set_stations(&stations).expect("failed to set station");
-        let v = stations
-            .iter()
-            .map(|x| x.name.to_owned())
-            .collect::<Vec<_>>();
+        let arg = Options::init();
+        match &arg.area {
+            Some(area) => self.set_area(area)?,
+            // every station, so --station can reach out of the area
+            None => self.set_stations(&self.all_stations())?,
+        }
+
+        if let Some(key) = &arg.station {
+            match self.find_station(key) {
+                Some(name) => {
+                    if arg.area.is_none() {
+                        self.set_region(self.region_of(&name));
+                    }
+                    return self.select_station(name).await;
+                },
+                None => terminal::print_warn(format!("unknown station {}", key)),
+            }
+        }
 
         loop {
-            match Select::new("station?", v.to_owned()).prompt() {
+            if arg.area.is_none() {
+                let (regions, home) = self.regions();
+                let region = Select::new("region?", regions)
+                    .with_scorer(&search::scorer)
+                    .with_starting_cursor(home)
+                    .raw_prompt();
+                match region {
+                    Ok(region) => self.set_region(region.index),
+                    Err(InquireError::OperationCanceled) => return Err(Error::from(Cancel)),
+                    Err(InquireError::OperationInterrupted) => {
+                        return Err(Error::from(OperationInterrupted))
+                    },
+                    Err(_) => continue,
+                }
+            }
+
+            let stations = self.current.stations.to_owned();
+            let scorer = |input: &str, _: &String, name: &str, i: usize| {
+                search::score(input, &[&stations[i].id, name])
+            };
+            match Select::new("station?", self.get_stations())
+                .with_scorer(&scorer)
+                .raw_prompt()
+            {
                 Ok(station) => {
-                    self.param.stations = v;
-                    self.current.station = Some(
-                        stations
-                            .iter()
-                            .find(|x| x.name == station)
-                            .ok_or(InquireError)?
-                            .to_owned(),
-                    );
+                    self.current.station = Some(stations[station.index].to_owned());
                     break;
                 },
-                Err(e) => match e {
-                    InquireError::OperationCanceled => return Err(Error::from(Cancel)),
-                    InquireError::OperationInterrupted => {
-                        return Err(Error::from(OperationInterrupted))
-                    },
-                    _ => continue,
+                // back to the regions
+                Err(InquireError::OperationCanceled) if arg.area.is_none() => continue,
+                Err(InquireError::OperationCanceled) => return Err(Error::from(Cancel)),
+                Err(InquireError::OperationInterrupted) => {
+                    return Err(Error::from(OperationInterrupted))
                 },
+                Err(_) => continue,
             };
         }
 
@@ -414,6 +476,9 @@ impl Api {
     }
 
     pub async fn select_station(&mut self, station: String) -> Result<()> {
+        if !self.current.stations.iter().any(|x| x.name == station) {
+            self.set_region(self.region_of(&station));
+        }
         self.current.station = Some(
             self.current
                 .stations
@@ -433,20 +498,151 @@ impl Api {
     }
 
     pub fn get_current_station(&self) -> Option<String> {
-        Some(self.clone().current.station.unwrap().name)
+        self.current.station.as_ref().map(|x| x.name.to_owned())
+    }
+
+    /// station name by id or name, exact or the closest match, in any area
+    pub fn find_station(&self, key: &str) -> Option<String> {
+        self.lookup(key).map(|x| x.name)
+    }
+
+    /// station by id or name, exact or the closest match, in any area
+    pub fn lookup(&self, key: &str) -> Option<Station> {
+        let stations = &self.all_stations();
+        stations
+            .iter()
+            .find(|x| x.id == key || x.name == key)
+            .or_else(|| search::best(key, stations, |x| vec![&x.id, &x.name]))
+            .cloned()
+    }
+
+    /// hook event about the current station
+    pub fn event(&self, kind: Kind) -> hooks::Event {
+        hooks::Event::new(kind).station(
+            self.current.station.as_ref().map(|x| x.name.as_str()),
+            self.current.station_id.as_deref(),
+        )
+    }
+
+    /// program on air, once known
+    pub fn program(&self) -> Option<&Prog> {
+        self.current.prog.as_ref()
+    }
+
+    /// end of the program on air, once known
+    pub fn program_end(&self) -> Option<NaiveDateTime> {
+        Some(self.current.to).filter(|x| *x != NaiveDateTime::default())
+    }
+
+    pub fn get_current_station_id(&self) -> Option<String> {
+        self.current.station_id.to_owned()
     }
 
     fn set_stations(&mut self, v: &Vec<Station>) -> Result<()> {
         self.current.stations = v.to_owned();
+        self.param.stations = v.iter().map(|x| x.name.to_owned()).collect();
         Ok(())
     }
 
+    fn all_stations(&self) -> Vec<Station> {
+        self.data
+            .region
+            .stations
+            .iter()
+            .flat_map(|x| x.station.to_owned())
+            .collect()
+    }
+
+    /// region names, and the one holding the current station or else the
+    /// detected area
+    pub fn regions(&self) -> (Vec<String>, usize) {
+        let regions = &self.data.region.stations;
+        let area = match &self.current.station {
+            Some(x) => Some(&x.area_id),
+            None => self.current.home.as_ref(),
+        };
+        let current = regions
+            .iter()
+            .position(|x| x.station.iter().any(|s| Some(&s.area_id) == area))
+            .unwrap_or_default();
+        (regions.iter().map(|x| x.region_name.to_owned()).collect(), current)
+    }
+
+    /// station names of a region
+    pub fn region_stations(&self, i: usize) -> Vec<String> {
+        self.data.region.stations[i]
+            .station
+            .iter()
+            .map(|x| x.name.to_owned())
+            .collect()
+    }
+
+    /// the stations next and prev go through become those of a region
+    pub fn set_region(&mut self, i: usize) {
+        let stations = self.data.region.stations[i].station.to_owned();
+        self.set_stations(&stations).expect("failed to set station");
+    }
+
+    /// region listing a station
+    fn region_of(&self, name: &str) -> usize {
+        self.data
+            .region
+            .stations
+            .iter()
+            .position(|x| x.station.iter().any(|s| s.name == name))
+            .unwrap_or_default()
+    }
+
+    /// the stations of an area such as JP13
+    pub fn stations_in(&self, area: &str) -> Result<Vec<Station>> {
+        let stations = self
+            .all_stations()
+            .into_iter()
+            .filter(|x| x.area_id.eq_ignore_ascii_case(area))
+            .collect::<Vec<_>>();
+        if stations.is_empty() {
+            return Err(Error::from(AreaError(area.to_string())));
+        }
+        Ok(stations)
+    }
+
+    /// only the stations of an area such as JP13
+    fn set_area(&mut self, area: &str) -> Result<()> {
+        let stations = self.stations_in(area)?;
+        self.set_stations(&stations)
+    }
+
+    /// area id detected from the address we connect from
+    pub fn detected_area(&self) -> Option<&str> {
+        self.current.area_id.as_deref()
+    }
+
+    /// area of the station on air, as `TOKYO JAPAN (JP13)`
+    pub fn area(&self) -> String {
+        let id = match &self.current.station {
+            Some(x) => &x.area_id,
+            None => return String::new(),
+        };
+        match area_name(id) {
+            Some(name) => format!("{} ({})", name, id),
+            None => id.to_owned(),
+        }
+    }
+
     async fn login_check(&mut self) -> Result<()> {
         match &self.url.check {
             None => {},
             Some(check) => {
-                self.backoff_request(&format!("{}{}", self.url.domain, check), None)
-                    .await?;
+                match self
+                    .backoff_request(&format!("{}{}", self.url.domain, check), None)
+                    .await
+                {
+                    Ok(_) => {},
+                    Err(e) => match e.downcast_ref::<RadicoError>() {
+                        Some(Status(400)) | Some(Unauthorized) => info!("not logged in\r"),
+                        _ => return Err(e),
+                    },
+                }
             },
         }
         Ok(())
@@ -481,9 +677,26 @@ impl Api {
         );
 
         headers.insert(self.key(5)?, auth_token.parse()?);
+        self.current.authed = Some(Instant::now());
         Ok(headers)
     }
 
+    fn token_expired(&self) -> bool {
+        self.current
+            .authed
+            .is_some_and(|x| x.elapsed() > TOKEN_TTL)
+    }
+
+    /// rerun the auth handshake and refresh the station url
+    pub async fn reauth(&mut self) -> Result<()> {
+        info!("reauth {:?}\r", self.current.station_id);
+        self.current.authed = None;
+        self.playlist_url().await?;
+        self.station_url().await?;
+        self.url.station.as_ref().ok_or(Forbidden)?;
+        Ok(())
+    }
+
     pub async fn station_url(&mut self) -> Result<()> {
         let hash = gen_hash_key();
 
@@ -510,14 +723,18 @@ impl Api {
             .await
         {
             Ok(res) => res,
-            Err(e) => return Err(e),
+            Err(e) => match e.downcast_ref::<RadicoError>() {
+                Some(Forbidden) => {
+                    warn!("station forbidden {}\r", station);
+                    self.url.station = None;
+                    return Ok(());
+                },
+                _ => return Err(e),
+            },
         };
 
         match res.text().await {
             Ok(list) => {
-                if list == "forbidden" {
-                    self.url.station = None;
-                }
                 self.url.station = list
                     .split("\n")
                     .filter(|x| x.contains("https://"))
@@ -530,30 +747,99 @@ impl Api {
         Ok(())
     }
 
-    pub async fn medialist(&mut self) -> Result<Vec<String>> {
-        match self.clone().url.station {
-            None => Err(Error::from(Forbidden)),
-            Some(url) => {
-                let res = match self.backoff_request(&url, None).await {
-                    Ok(res) => res,
-                    Err(e) => return Err(e),
-                };
+    pub async fn medialist(&mut self) -> Result<MediaPlaylist> {
+        if self.token_expired() {
+            self.reauth().await?;
+        }
 
-                let body = res.text().await.expect("medialist response error");
-                Ok(body
-                    .split("\n")
-                    .filter(|x| x.contains("https://"))
-                    .map(|x| x.to_string())
-                    .collect::<Vec<_>>())
+        let res = match self.station_request().await {
+            Err(e) if auth_error(&e) => {
+                warn!("medialist: {}\r", e);
+                self.reauth().await?;
+                self.station_request().await?
             },
+            res => res?,
+        };
+
+        let base = res.url().to_owned();
+        let body = res.text().await?;
+        body.parse::<MediaPlaylist>()?.resolve(&base)
+    }
+
+    /// segments of a past program from `seek` on, as many as the server
+    /// hands out at once
+    pub async fn timefree(
+        &mut self,
+        station_id: &str,
+        prog: &Prog,
+        seek: NaiveDateTime,
+    ) -> Result<MediaPlaylist> {
+        let header = match &self.current.timefree {
+            Some(header) if !self.token_expired() => header.to_owned(),
+            _ => self.get_auth_token().await?,
+        };
+        self.current.timefree = Some(header.to_owned());
+
+        let url = format!(
+            "{}{}?station_id={}&l=15&ft={}&to={}&seek={}",
+            self.url.domain,
+            TIMEFREE_PATH,
+            station_id,
+            prog.ft,
+            prog.to,
+            seek.format("%Y%m%d%H%M%S")
+        );
+        let res = match self.backoff_request(&url, Some(header)).await {
+            Err(e) if auth_error(&e) => {
+                warn!("timefree: {}\r", e);
+                self.current.timefree = None;
+                let header = self.get_auth_token().await?;
+                self.backoff_request(&url, Some(header)).await?
+            },
+            res => res?,
+        };
+        let body = res.text().await?;
+        let chunklist = body
+            .lines()
+            .find(|x| x.contains("https://"))
+            .ok_or(PlaylistError)?
+            .to_owned();
+
+        let res = self.backoff_request(&chunklist, None).await?;
+        let base = res.url().to_owned();
+        res.text().await?.parse::<MediaPlaylist>()?.resolve(&base)
+    }
+
+    async fn station_request(&mut self) -> Result<Response> {
+        match self.clone().url.station {
+            None => Err(Error::from(Forbidden)),
+            Some(url) => self.backoff_request(&url, None).await,
         }
     }
 
     pub async fn get_aac(&mut self, url: &str) -> Result<Vec<u8>> {
-        match self.backoff_request(url, None).await {
-            Ok(r) => Ok(r.bytes().await?.to_vec()),
-            Err(e) => Err(e),
+        let res = match self.backoff_request(url, None).await {
+            Err(e) if auth_error(&e) => {
+                warn!("get_aac: {}\r", e);
+                self.reauth().await?;
+                self.backoff_request(url, None).await?
+            },
+            res => res?,
+        };
+        Ok(res.bytes().await?.to_vec())
+    }
+
+    /// fetch a segment, remuxing fragmented MP4 to ADTS with the init section
+    pub async fn get_segment(&mut self, url: &str, map: Option<&str>) -> Result<Vec<u8>> {
+        let buf = self.get_aac(url).await?;
+        let Some(map) = map else { return Ok(buf) };
+
+        if self.current.init.as_ref().is_none_or(|(x, _)| x != map) {
+            let init = self.get_aac(map).await?;
+            self.current.init = Some((map.to_string(), init));
         }
+        let (_, init) = self.current.init.as_ref().unwrap();
+        Ok(redlux::remux_fragment(init, &buf)?)
     }
 
     async fn request(&mut self, url: &str) -> Result<String> {
@@ -562,9 +848,8 @@ impl Api {
         Ok(body)
     }
 
-    #[async_recursion]
     async fn backoff_request(&mut self, url: &str, header: Option<HeaderMap>) -> Result<Response> {
-        let mut count = 0_i8;
+        let mut attempt = 0_u32;
 
         let client = match header {
             None => self.client.get(url),
@@ -572,6 +857,7 @@ impl Api {
         };
 
         loop {
+            attempt += 1;
             let res = match client.try_clone() {
                 None => {
                     info!("client error\r");
@@ -580,45 +866,41 @@ impl Api {
                 Some(client) => client.send().await,
             };
 
-            match res {
-                Ok(res) => match res.status().as_u16() {
-                    200..=299 => {
-                        info!("Fetching {} {}\r", res.status(), url);
-                        return Ok(res);
-                    },
-                    400 => {
-                        warn!("bad request {} {}\r", res.status(), url);
-                        return Ok(res);
-                    },
-                    403 => {
-                        error!("forbidden {} {}\r", res.status(), url);
+            let delay = match res {
+                Ok(res) => match res.status() {
+                    status if status.is_success() => {
+                        info!("Fetching {} {}\r", status, url);
                         return Ok(res);
                     },
-                    401..=499 => {
-                        error!("client error {} {}\r", res.status(), url);
-                        self.station_url().await?;
+                    status if retry::retryable(status) => {
+                        warn!("retry {} {}\r", status, url);
+                        if attempt >= self.retry.max_attempts {
+                            let e = Error::from(Status(status.as_u16()));
+                            return Err(e.context(RetryExhausted(attempt)));
+                        }
+                        self.retry.delay(attempt, Some(&res))
                     },
-                    500..=599 => {
-                        error!("server error {} {}\r", res.status(), url);
-                        self.station_url().await?;
-                    },
-                    _ => {
-                        warn!("other {} {}\r", res.status(), url);
-                        self.station_url().await?;
+                    status => {
+                        error!("{} {}\r", status, url);
+                        return Err(Error::from(match status.as_u16() {
+                            401 => Unauthorized,
+                            403 => Forbidden,
+                            n => Status(n),
+                        }));
                     },
                 },
                 Err(e) => {
                     error!("fetch error: {}\r", e);
-                    if count > 1 {
+                    if e.is_builder() {
                         return Err(Error::from(e));
                     }
+                    if attempt >= self.retry.max_attempts {
+                        return Err(Error::from(e).context(RetryExhausted(attempt)));
+                    }
+                    self.retry.delay(attempt, None)
                 },
             };
-            if count > 1 {
-                return Err(Error::from(ClientError));
-            }
-            count += 1;
-            tokio::time::sleep(Duration::from_millis(200)).await;
+            tokio::time::sleep(delay).await;
         }
     }
 
@@ -673,26 +955,31 @@ impl Api {
         Ok(auth_token.to_string())
     }
 
-    pub async fn current_prog(&mut self) -> Result<()> {
-        if self.f1.load(Relaxed) { return Ok(()) }
-        let l = (Local::now() - Duration::from_secs(18000))
-            .format("%Y%m%d")
-            .to_string();
+    /// logo image of a station, the largest the region list offers
+    pub async fn logo(&mut self, station: &Station) -> Result<Vec<u8>> {
+        let url = match station.logo_url() {
+            Some(url) => url.to_string(),
+            None => format!("{}{}/{}/224x100.png", self.url.domain, LOGO_PATH, station.id),
+        };
+        let res = self.backoff_request(&url, None).await?;
+        Ok(res.bytes().await?.to_vec())
+    }
 
+    /// programs of a station on a broadcast day
+    pub async fn guide(&mut self, station_id: &str, date: NaiveDate) -> Result<Vec<Prog>> {
         let res = self
             .backoff_request(
                 &format!(
                     "{}/{}/{}/{}.xml",
                     self.url.domain,
                     self.to_owned().url.prog.unwrap(),
-                    l,
-                    &self.current.station_id.to_owned().unwrap()
+                    date.format("%Y%m%d"),
+                    station_id
                 ),
                 None,
             )
             .await?;
         let body = res.text().await?;
-        let station = &self.to_owned().current.station.unwrap().name;
         let current: CurrentProg = match from_str(&body) {
             Ok(a) => a,
             Err(e) => {
@@ -700,21 +987,44 @@ impl Api {
                 return Err(Error::from(e));
             },
         };
-        if let Some(i) = current.stations.station.progs.prog.iter().rev().find(|x| {
-            NaiveDateTime::parse_from_str(&x.ft, "%Y%m%d%H%M%S").unwrap()
-                < Local::now().naive_local()
-        }) {
+        Ok(current.stations.station.progs.prog)
+    }
+
+    pub async fn current_prog(&mut self) -> Result<()> {
+        if self.f1.load(Relaxed) { return Ok(()) }
+        let station_id = self.current.station_id.to_owned().unwrap();
+        let now = jst_now();
+        let prog = match guide::on_air(self, &station_id, now).await? {
+            Some(prog) => Some(prog),
+            None => guide::last_started(self, &station_id, now).await?,
+        };
+        guide::prefetch(self, &station_id);
+        let station = &self.to_owned().current.station.unwrap().name;
+        if let Some(i) = &prog {
             terminal::clear_screen();
             self.current.to = NaiveDateTime::parse_from_str(&i.to, "%Y%m%d%H%M%S")?;
+            if self.current.prog.as_ref().is_none_or(|x| x.ft != i.ft || x.title != i.title) {
+                self.current.prog = Some(i.clone());
+                let mut event = self.event(Kind::ProgramChange);
+                event.title = Some(i.title.to_owned());
+                event.start = Some(i.ft.to_owned());
+                event.end = Some(i.to.to_owned());
+                event.info = Some(strip_html(&i.info).trim().to_string());
+                hooks::emit(event);
+            }
 
             println!(
-                "{}\n\r{} - {} {}\n\r{}\r",
+                "{}\n\r{} - {} {}\n\r{}{}\r",
                 station,
                 NaiveDateTime::parse_from_str(&i.ft, "%Y%m%d%H%M%S")
                     .unwrap()
                     .format("%H:%M"),
                 self.current.to.format("%H:%M"),
                 i.title,
+                match i.pfm.trim() {
+                    "" => String::new(),
+                    pfm => format!("{}\n\r", pfm),
+                },
                 strip_html(&i.info).trim()
             );
         }
@@ -722,20 +1032,19 @@ impl Api {
         Ok(())
     }
 
-    pub async fn duration(&mut self, ave: Duration, instant: Instant) -> Duration {
-        let local = Local::now().naive_local();
+    pub async fn duration(&mut self, ave: Duration, delay: Duration, instant: Instant) -> Duration {
+        let local = jst_now();
 
         let prog_end = (self.current.to - (local - ave)).num_milliseconds();
-        let mut delay = Duration::from_secs(5);
-        info!("{:?} {:?} {:?}\r", local, ave, self.current.to);
-        if let 0..=5000 = prog_end {
-            delay = Duration::from_millis(prog_end as u64);
+        info!("{:?} {:?} {:?} {:?}\r", local, ave, delay, self.current.to);
+        if (0..=delay.as_millis() as i64).contains(&prog_end) {
+            Duration::from_millis(prog_end as u64)
         } else if local - ave > self.current.to {
             self.current_prog().await.unwrap();
+            delay
         } else {
-            delay = sleep(instant.elapsed());
+            sleep(delay, instant.elapsed())
         }
-        delay
     }
 
     fn key(&self, n: usize) -> core::result::Result<HeaderName, InvalidHeaderName> {
@@ -746,15 +1055,19 @@ impl Api {
     }
 }
 
-fn sleep(elapsed: Duration) -> Duration {
-    let mut delay = Duration::from_secs(5);
-    if Duration::new(5, 0) > elapsed {
-        delay = Duration::from_secs(5) - elapsed;
-    }
-    delay
+/// the token was rejected or the stream is not available to this session
+fn auth_error(e: &Error) -> bool {
+    matches!(
+        e.downcast_ref::<RadicoError>(),
+        Some(Unauthorized) | Some(Forbidden)
+    )
+}
+
+fn sleep(delay: Duration, elapsed: Duration) -> Duration {
+    delay.saturating_sub(elapsed)
 }
 
-fn strip_html(source: &str) -> String {
+pub(crate) fn strip_html(source: &str) -> String {
     let result = REG_CONDENSE
         .replace_all(source, " ")
         .cjk_compat_variants()
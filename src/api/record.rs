@@ -0,0 +1,178 @@
+use crate::api::download::{self, file_name, Job};
+use crate::api::guide::jst_now;
+use crate::api::timefree::{parse, program_at};
+use crate::api::xml::{Prog, Station};
+use crate::api::Api;
+use crate::terminal::args::Format;
+use crate::terminal::table;
+use crate::util::store;
+use anyhow::Result;
+use chrono::{NaiveDateTime, TimeDelta};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// recordings waiting for their program to air, an array of [`Recording`]
+const SCHEDULE_FILE: &str = "recordings.json";
+/// a program can be fetched a little after it ends
+const AVAILABLE_AFTER: TimeDelta = TimeDelta::minutes(10);
+/// past programs are kept for a week
+const EXPIRES_AFTER: TimeDelta = TimeDelta::days(7);
+/// how often `record --wait` looks at the schedule when nothing is due
+const IDLE: Duration = Duration::from_secs(15 * 60);
+
+/// a program to save once it has aired
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub station_id: String,
+    pub station: String,
+    pub title: String,
+    /// YYYYMMDDhhmmss
+    pub ft: String,
+    pub to: String,
+}
+
+impl Recording {
+    pub fn new(station: &Station, prog: &Prog) -> Self {
+        Recording {
+            station_id: station.id.to_owned(),
+            station: station.name.to_owned(),
+            title: prog.title.to_owned(),
+            ft: prog.ft.to_owned(),
+            to: prog.to.to_owned(),
+        }
+    }
+
+    /// when the program can be fetched
+    fn due(&self) -> Option<NaiveDateTime> {
+        Some(parse(&self.to).ok()? + AVAILABLE_AFTER)
+    }
+}
+
+/// add a program to the schedule, false if it is there already
+pub fn schedule(recording: Recording) -> Result<bool> {
+    let mut v: Vec<Recording> = store::load(SCHEDULE_FILE);
+    if v.contains(&recording) {
+        return Ok(false);
+    }
+    v.push(recording);
+    v.sort_by(|a, b| a.ft.cmp(&b.ft));
+    store::save(SCHEDULE_FILE, &v)?;
+    Ok(true)
+}
+
+fn unschedule(recording: &Recording) -> Result<()> {
+    let mut v: Vec<Recording> = store::load(SCHEDULE_FILE);
+    v.retain(|x| x != recording);
+    store::save(SCHEDULE_FILE, &v)
+}
+
+fn print(format: Format) {
+    let v: Vec<Recording> = store::load(SCHEDULE_FILE);
+    let rows = v
+        .into_iter()
+        .map(|x| vec![x.ft, x.to, x.station_id, x.title])
+        .collect::<Vec<_>>();
+    table::print(format, &["ft", "to", "station", "title"], &rows);
+}
+
+/// fetch a due recording into `dir`
+async fn save(api: &mut Api, recording: &Recording, dir: &Option<PathBuf>) -> Result<()> {
+    let station = api.lookup(&recording.station_id).unwrap_or_else(|| Station {
+        id: recording.station_id.to_owned(),
+        name: recording.station.to_owned(),
+        ..Default::default()
+    });
+    let from = parse(&recording.ft)?;
+    let prog = program_at(api, &station.id, from).await?;
+    let name = file_name(&station.id, from);
+    let job = Job {
+        station,
+        prog,
+        from,
+        to: parse(&recording.to)?,
+        output: dir.as_ref().map_or(PathBuf::from(&name), |x| x.join(&name)),
+        jobs: download::JOBS,
+        logo: true,
+    };
+    download::fetch(api, &job).await
+}
+
+/// save the due recordings, keeping those that failed for another try until
+/// they expire
+async fn save_all(api: &mut Api, due: Vec<&Recording>, dir: &Option<PathBuf>, now: NaiveDateTime) -> Result<()> {
+    for recording in due {
+        eprintln!("{} {} {}", recording.ft, recording.station, recording.title);
+        match save(api, recording, dir).await {
+            Ok(()) => unschedule(recording)?,
+            Err(e) if recording.due().is_none_or(|at| at + EXPIRES_AFTER <= now) => {
+                error!("record: {:?}", e);
+                eprintln!("{}: gone, {}", recording.title, e);
+                unschedule(recording)?;
+            },
+            Err(e) => {
+                error!("record: {:?}", e);
+                eprintln!("{}: {}, will retry", recording.title, e);
+            },
+        }
+    }
+    Ok(())
+}
+
+/// a logged in session; unlike `Api::init` a failure is returned, so that
+/// `record --wait` can try again later
+async fn session(url: &str) -> Result<Api> {
+    let mut api = Api::new(url.to_string());
+    api.initializer().await?;
+    api.login_check().await?;
+    Ok(api)
+}
+
+/// `radico record`: save the scheduled programs that have aired, and with
+/// `wait` keep at it as the others air
+pub async fn run(wait: bool, list: bool, format: Format, output: Option<PathBuf>, url: String) -> Result<()> {
+    if list {
+        print(format);
+        return Ok(());
+    }
+
+    loop {
+        let now = jst_now();
+        let v: Vec<Recording> = store::load(SCHEDULE_FILE);
+        let due = v
+            .iter()
+            .filter(|x| x.due().is_none_or(|at| at <= now))
+            .collect::<Vec<_>>();
+
+        if !due.is_empty() {
+            // a fresh session each time, tokens do not last for days
+            match session(&url).await {
+                Ok(mut api) => save_all(&mut api, due, &output, now).await?,
+                Err(e) if wait => {
+                    // the network may be back next time round
+                    error!("record: {:?}", e);
+                    eprintln!("{}, will retry", e);
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        if !wait {
+            return Ok(());
+        }
+
+        // sleep until the next one is due, looking again now and then for
+        // recordings added meanwhile
+        let v: Vec<Recording> = store::load(SCHEDULE_FILE);
+        let now = jst_now();
+        let next = v
+            .iter()
+            .filter_map(|x| x.due())
+            .filter(|at| *at > now)
+            .min()
+            .and_then(|at| (at - now).to_std().ok())
+            .unwrap_or(IDLE);
+        info!("record: sleep {:?}\r", next.min(IDLE));
+        tokio::time::sleep(next.min(IDLE)).await;
+    }
+}
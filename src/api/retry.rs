@@ -0,0 +1,131 @@
+use crate::terminal::args::Options;
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, Rng};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use std::time::Duration;
+
+/// retry policy for `Api::backoff_request`
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// attempts including the first request
+    pub max_attempts: u32,
+    /// delay before the first retry, doubled on every attempt
+    pub base: Duration,
+    /// upper bound of a single delay, `Retry-After` included
+    pub max: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: Options::init().retries.unwrap_or(4).max(1),
+            base: Duration::from_millis(200),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// exponential backoff with full jitter
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let cap = self
+            .base
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max);
+        Duration::from_millis(thread_rng().gen_range(0..=cap.as_millis() as u64))
+    }
+
+    /// delay before the next attempt, honoring `Retry-After`
+    pub fn delay(&self, attempt: u32, res: Option<&Response>) -> Duration {
+        match res.and_then(retry_after) {
+            Some(d) => d.min(self.max),
+            None => self.backoff(attempt),
+        }
+    }
+}
+
+/// statuses worth another attempt, the rest are fatal
+pub fn retryable(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 425 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// `Retry-After` as delay-seconds or HTTP-date
+pub fn retry_after(res: &Response) -> Option<Duration> {
+    let v = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = v.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(v).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            base: Duration::from_millis(200),
+            max: Duration::from_secs(10),
+        }
+    }
+
+    fn response(retry_after: &str) -> Response {
+        http::Response::builder()
+            .status(503)
+            .header(RETRY_AFTER, retry_after)
+            .body("")
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = policy();
+        // full jitter under a cap doubling from the base
+        for (attempt, cap) in [(1, 200), (2, 400), (3, 800), (6, 6400)] {
+            for _ in 0..100 {
+                assert!(policy.backoff(attempt) <= Duration::from_millis(cap));
+            }
+        }
+        // up to the max, however many attempts
+        for attempt in [7, 20, 64, u32::MAX] {
+            assert!(policy.backoff(attempt) <= policy.max);
+        }
+        // jittered, not always the cap
+        let delays = (0..100).map(|_| policy.backoff(5)).collect::<Vec<_>>();
+        assert!(delays.iter().any(|x| *x != delays[0]));
+    }
+
+    #[test]
+    fn retry_after_header() {
+        let policy = policy();
+        assert_eq!(retry_after(&response("3")), Some(Duration::from_secs(3)));
+        assert_eq!(policy.delay(1, Some(&response("3"))), Duration::from_secs(3));
+        // capped by the policy
+        assert_eq!(policy.delay(1, Some(&response("120"))), policy.max);
+
+        let date = (Utc::now() + TimeDelta::seconds(30)).to_rfc2822();
+        let delay = retry_after(&response(&date)).unwrap();
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30), "{:?}", delay);
+        // already past
+        let date = (Utc::now() - TimeDelta::seconds(30)).to_rfc2822();
+        assert_eq!(retry_after(&response(&date)), None);
+
+        assert_eq!(retry_after(&response("soon")), None);
+        assert!(policy.delay(1, Some(&response("soon"))) <= policy.base);
+    }
+
+    #[test]
+    fn statuses() {
+        for n in [408, 425, 429, 500, 502, 503, 504] {
+            assert!(retryable(StatusCode::from_u16(n).unwrap()), "{}", n);
+        }
+        for n in [200, 400, 401, 403, 404, 501] {
+            assert!(!retryable(StatusCode::from_u16(n).unwrap()), "{}", n);
+        }
+    }
+}
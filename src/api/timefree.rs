@@ -0,0 +1,441 @@
+use crate::api::guide::{self, jst_now};
+use crate::api::hls::Segment;
+use crate::api::worker::naive_date_from;
+use crate::api::xml::Prog;
+use crate::api::Api;
+use crate::audio::player::Player;
+use crate::errors::RadicoError::{MissingStation, NoProgram, OperationInterrupted, TimeError, UnknownStation};
+use crate::terminal;
+use crate::terminal::args::usage;
+use anyhow::{Error, Result};
+use chrono::{NaiveDateTime, TimeDelta};
+use crossterm::event::{self, poll, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::enable_raw_mode;
+use log::info;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// how far ahead of playback segments are fetched
+const AHEAD: Duration = Duration::from_secs(60);
+/// seek with left/right
+const SHORT_SEEK: TimeDelta = TimeDelta::seconds(30);
+/// seek with down/up
+const LONG_SEEK: TimeDelta = TimeDelta::minutes(5);
+const BAR_WIDTH: usize = 30;
+
+pub(crate) fn parse(s: &str) -> Result<NaiveDateTime> {
+    Ok(NaiveDateTime::parse_from_str(s, "%Y%m%d%H%M%S")?)
+}
+
+/// a time given on the command line, such as 2026-10-17T21:00
+pub fn parse_time(s: &str) -> Result<NaiveDateTime> {
+    let formats = [
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y%m%d%H%M",
+        "%Y%m%d%H%M%S",
+    ];
+    let at = formats
+        .iter()
+        .find_map(|x| NaiveDateTime::parse_from_str(s, x).ok())
+        .ok_or(TimeError(s.to_string()))?;
+    Ok(at)
+}
+
+/// the program that was on air at a past time
+pub async fn program_at(api: &mut Api, station_id: &str, at: NaiveDateTime) -> Result<Prog> {
+    let prog = guide::on_air(api, station_id, at)
+        .await?
+        .filter(|_| at < jst_now())
+        .ok_or(NoProgram(at.to_string()))?;
+    Ok(prog)
+}
+
+/// program time a segment starts at, if its url tells
+pub(crate) fn segment_start(
+    segment: &Segment,
+    (ft, to): (NaiveDateTime, NaiveDateTime),
+) -> Option<NaiveDateTime> {
+    naive_date_from(&segment.url)
+        .ok()
+        .filter(|x| (ft..=to).contains(x))
+}
+
+/// a playlist window without the segments up to `last` or before `cursor`,
+/// as the server may start it before the seek or hand out the same one again
+pub(crate) fn trim(
+    segments: Vec<Segment>,
+    last: Option<&String>,
+    cursor: NaiveDateTime,
+    prog: (NaiveDateTime, NaiveDateTime),
+) -> VecDeque<Segment> {
+    let mut segments = VecDeque::from(segments);
+    if let Some(i) = segments.iter().position(|x| Some(&x.url) == last) {
+        segments.drain(..=i);
+    }
+    segments.retain(|x| {
+        segment_start(x, prog)
+            .is_none_or(|at| at + TimeDelta::from_std(x.duration).unwrap_or_default() > cursor)
+    });
+    segments
+}
+
+/// catch-up playback of a past program
+pub struct Timefree {
+    station_id: String,
+    prog: Prog,
+    ft: NaiveDateTime,
+    to: NaiveDateTime,
+    /// program time of the next segment to fetch
+    cursor: NaiveDateTime,
+    /// program time where the audio fed since the last seek starts
+    base: NaiveDateTime,
+    segments: VecDeque<Segment>,
+    map: Option<String>,
+    /// url of the last segment fed
+    last: Option<String>,
+    fed_bytes: usize,
+    fed: Duration,
+    /// nothing left to fetch
+    done: bool,
+    /// the last fetch failed, reported once
+    failing: bool,
+}
+
+impl Timefree {
+    pub fn new(station_id: &str, prog: Prog) -> Result<Self> {
+        let (ft, to) = (parse(&prog.ft)?, parse(&prog.to)?);
+        Ok(Timefree {
+            station_id: station_id.to_string(),
+            prog,
+            ft,
+            to,
+            cursor: ft,
+            base: ft,
+            segments: VecDeque::new(),
+            map: None,
+            last: None,
+            fed_bytes: 0,
+            fed: Duration::ZERO,
+            done: false,
+            failing: false,
+        })
+    }
+
+    /// audio fed but not heard yet, given the bytes still buffered
+    fn buffered(&self, len: usize) -> Duration {
+        if self.fed_bytes == 0 {
+            return Duration::ZERO;
+        }
+        self.fed
+            .mul_f64(len.min(self.fed_bytes) as f64 / self.fed_bytes as f64)
+    }
+
+    /// program time being heard
+    pub fn position(&self, len: usize) -> NaiveDateTime {
+        let played = self.fed - self.buffered(len);
+        self.base + TimeDelta::from_std(played).unwrap_or_default()
+    }
+
+    /// jump by `delta` from the position heard, within the program
+    pub fn seek(&mut self, len: usize, delta: TimeDelta) {
+        let at = (self.position(len) + delta).clamp(self.ft, self.to);
+        info!("seek {}\r", at);
+        self.cursor = at;
+        self.base = at;
+        self.segments.clear();
+        self.last = None;
+        self.fed_bytes = 0;
+        self.fed = Duration::ZERO;
+        self.done = at >= self.to;
+    }
+
+    /// fetched and heard to the end
+    pub fn ended(&self, len: usize) -> bool {
+        self.done && len == 0
+    }
+
+    /// fetch segments until a minute is buffered
+    pub async fn fill(&mut self, api: &Mutex<Api>, player: &Mutex<Player>) -> Result<()> {
+        loop {
+            {
+                let player = player.lock().await;
+                if self.done
+                    || player.buffer_full()
+                    || self.buffered(player.buffer_length()) >= AHEAD
+                {
+                    return Ok(());
+                }
+            }
+
+            let Some(segment) = self.segments.pop_front() else {
+                let playlist = api
+                    .lock()
+                    .await
+                    .timefree(&self.station_id, &self.prog, self.cursor)
+                    .await?;
+                self.map = playlist.map;
+                self.segments = trim(playlist.segments, self.last.as_ref(), self.cursor, (self.ft, self.to));
+                if self.segments.is_empty() {
+                    info!("timefree: no more segments at {}\r", self.cursor);
+                    self.done = true;
+                }
+                continue;
+            };
+
+            let buf = api
+                .lock()
+                .await
+                .get_segment(&segment.url, self.map.as_deref())
+                .await?;
+            let start = segment_start(&segment, (self.ft, self.to)).unwrap_or(self.cursor);
+            if self.fed_bytes == 0 {
+                self.base = start;
+            }
+            self.cursor = start + TimeDelta::from_std(segment.duration).unwrap_or_default();
+            self.done = self.cursor >= self.to;
+            self.fed_bytes += buf.len();
+            self.fed += segment.duration;
+            self.last = Some(segment.url);
+            player.lock().await.add(&buf);
+        }
+    }
+
+    /// status line: position, bar, end time
+    fn progress(&self, len: usize, paused: bool) -> String {
+        let pos = self.position(len);
+        let total = (self.to - self.ft).num_seconds().max(1);
+        let heard = (pos - self.ft).num_seconds().clamp(0, total);
+        let n = (heard * BAR_WIDTH as i64 / total) as usize;
+        format!(
+            "{} {} [{}{}] {} {}%",
+            if paused { "||" } else { "> " },
+            pos.format("%H:%M:%S"),
+            "=".repeat(n),
+            "-".repeat(BAR_WIDTH - n),
+            self.to.format("%H:%M:%S"),
+            heard * 100 / total
+        )
+    }
+}
+
+/// what a key does during catch-up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Pause,
+    Volume(char),
+    Seek(TimeDelta),
+    Back,
+}
+
+impl Key {
+    pub fn from_code(code: KeyCode) -> Option<Key> {
+        let key = match code {
+            KeyCode::Char(' ') | KeyCode::Char('k') => Key::Pause,
+            KeyCode::Char(c @ '0'..='9') => Key::Volume(c),
+            KeyCode::Char('q') | KeyCode::Esc => Key::Back,
+            KeyCode::Left => Key::Seek(-SHORT_SEEK),
+            KeyCode::Right => Key::Seek(SHORT_SEEK),
+            KeyCode::Down => Key::Seek(-LONG_SEEK),
+            KeyCode::Up => Key::Seek(LONG_SEEK),
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+impl Timefree {
+    /// clear the screen for the program and tell the keys
+    pub fn header(&self, station: &str) {
+        terminal::clear_screen();
+        println!(
+            "{}\n\r{} - {} {}\n\r\n\rspace pause, left/right 30 s, down/up 5 min, q back\r",
+            station,
+            self.ft.format("%m/%d %H:%M"),
+            self.to.format("%H:%M"),
+            self.prog.title
+        );
+    }
+
+    /// fetch ahead and return the status line, none once heard to the end
+    pub async fn step(&mut self, api: &Mutex<Api>, player: &Mutex<Player>) -> Option<String> {
+        match self.fill(api, player).await {
+            Ok(()) => self.failing = false,
+            Err(e) if !self.failing => {
+                terminal::print_error(&e);
+                self.failing = true;
+            },
+            Err(_) => {},
+        }
+        let (len, paused) = {
+            let player = player.lock().await;
+            (player.buffer_length(), player.is_paused())
+        };
+        if self.ended(len) {
+            return None;
+        }
+        Some(self.progress(len, paused))
+    }
+
+    /// carry out a key, false to go back
+    pub async fn key(&mut self, key: Key, player: &Mutex<Player>) -> bool {
+        let mut player = player.lock().await;
+        match key {
+            Key::Pause => {
+                let paused = player.is_paused();
+                player.pause(!paused);
+            },
+            Key::Volume(c) => player.volume(c),
+            Key::Seek(delta) => {
+                self.seek(player.buffer_length(), delta);
+                player.buffer_clear();
+            },
+            Key::Back => return false,
+        }
+        true
+    }
+}
+
+/// play a program until it ends or `q` is pressed
+pub async fn run(api: &Mutex<Api>, player: &Mutex<Player>, station: &str, mut tf: Timefree) -> Result<()> {
+    player.lock().await.buffer_clear();
+    tf.header(station);
+
+    enable_raw_mode()?;
+    loop {
+        player.lock().await.tick();
+        let Some(status) = tf.step(api, player).await else {
+            break;
+        };
+        terminal::print_status(status);
+
+        if !poll(Duration::from_millis(200))? {
+            continue;
+        }
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        if code == KeyCode::Char('c') && modifiers == KeyModifiers::CONTROL {
+            terminal::quit(Error::from(OperationInterrupted));
+        }
+        match Key::from_code(code) {
+            Some(key) if !tf.key(key, player).await => break,
+            _ => {},
+        }
+    }
+
+    terminal::print_status("");
+    player.lock().await.pause(false);
+    Ok(())
+}
+
+/// `radico play --station --at`: the program on air at a past time, on its own
+pub async fn play(station: Option<String>, at: String, url: Option<String>) -> Result<()> {
+    let Some(url) = url else {
+        println!("{}", usage());
+        return Ok(());
+    };
+    let key = station.ok_or(MissingStation)?;
+    let at = parse_time(&at)?;
+
+    let mut api = Api::new(url);
+    api.init().await?;
+    let station = api.lookup(&key).ok_or(UnknownStation(key))?;
+    let prog = program_at(&mut api, &station.id, at).await?;
+
+    let player = Mutex::new(Player::default());
+    let tf = Timefree::new(&station.id, prog)?;
+    let res = run(&Mutex::new(api), &player, &station.name, tf).await;
+    terminal::restore();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime { parse_time(s).unwrap() }
+
+    /// an hour long program, 10 s over 1000 bytes fed from 21:30
+    fn timefree() -> Timefree {
+        let prog = Prog {
+            ft: "20261017210000".to_string(),
+            to: "20261017220000".to_string(),
+            title: "program".to_string(),
+            ..Default::default()
+        };
+        let mut tf = Timefree::new("TBS", prog).unwrap();
+        tf.base = at("2026-10-17T21:30");
+        tf.fed = Duration::from_secs(10);
+        tf.fed_bytes = 1000;
+        tf
+    }
+
+    #[test]
+    fn buffered() {
+        let mut tf = timefree();
+        assert_eq!(tf.buffered(250), Duration::from_millis(2500));
+        assert_eq!(tf.buffered(0), Duration::ZERO);
+        // bytes of an earlier seek still buffered
+        assert_eq!(tf.buffered(5000), Duration::from_secs(10));
+        tf.fed_bytes = 0;
+        assert_eq!(tf.buffered(250), Duration::ZERO);
+    }
+
+    #[test]
+    fn position() {
+        let tf = timefree();
+        assert_eq!(tf.position(1000), at("2026-10-17T21:30"));
+        assert_eq!(tf.position(500), at("2026-10-17T21:30:05"));
+        assert_eq!(tf.position(0), at("2026-10-17T21:30:10"));
+    }
+
+    #[test]
+    fn seek() {
+        let mut tf = timefree();
+        tf.seek(0, TimeDelta::seconds(30));
+        assert_eq!((tf.cursor, tf.base), (at("2026-10-17T21:30:40"), at("2026-10-17T21:30:40")));
+        assert_eq!((tf.fed, tf.fed_bytes, tf.done), (Duration::ZERO, 0, false));
+
+        // within the program
+        tf.seek(0, -TimeDelta::hours(1));
+        assert_eq!(tf.cursor, at("2026-10-17T21:00"));
+        tf.seek(0, TimeDelta::hours(2));
+        assert_eq!(tf.cursor, at("2026-10-17T22:00"));
+        assert!(tf.done && tf.ended(0) && !tf.ended(1));
+    }
+
+    #[test]
+    fn progress() {
+        let tf = timefree();
+        assert_eq!(
+            tf.progress(0, false),
+            format!(">  21:30:10 [{}{}] 22:00:00 50%", "=".repeat(15), "-".repeat(15))
+        );
+        let mut tf = timefree();
+        tf.base = at("2026-10-17T21:59:55");
+        assert_eq!(
+            tf.progress(0, true),
+            format!("|| 22:00:05 [{}] 22:00:00 100%", "=".repeat(BAR_WIDTH))
+        );
+        tf.seek(0, -TimeDelta::hours(2));
+        assert_eq!(tf.progress(0, false), format!(">  21:00:00 [{}] 22:00:00 0%", "-".repeat(BAR_WIDTH)));
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(Key::from_code(KeyCode::Char(' ')), Some(Key::Pause));
+        assert_eq!(Key::from_code(KeyCode::Char('7')), Some(Key::Volume('7')));
+        assert_eq!(Key::from_code(KeyCode::Down), Some(Key::Seek(-LONG_SEEK)));
+        assert_eq!(Key::from_code(KeyCode::Esc), Some(Key::Back));
+        assert_eq!(Key::from_code(KeyCode::Char('g')), None);
+    }
+}
@@ -1,12 +1,22 @@
+use crate::api::command::{Command, Request};
+use crate::api::connectivity::{network_error, Connectivity, Fetch, Link};
+use crate::api::timefree::{Key, Timefree};
+use crate::api::guide::{self, broadcast_date, jst_now};
 use crate::api::Api;
 use crate::audio::assets::ASSETS;
 use crate::audio::player::Player;
-use crate::errors::RadicoError::{Forbidden, OperationInterrupted};
+use crate::errors::RadicoError::{Forbidden, OperationInterrupted, StationError};
 use crate::util::menu;
 use crate::util::sleep::HalfSleep;
 use crate::util::state::StateCollector;
+use crate::util::status::{NowPlaying, StatusFile};
+use crate::util::alarm::{AlarmClock, Event as AlarmEvent};
+use crate::util::hooks::{self, Kind};
+use crate::util::ipc;
+use crate::util::timer::{SleepTimer, Timer};
+use crate::terminal::args::Options;
 use crate::{lazy_regex, terminal};
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
 use chrono::{Local, NaiveDateTime};
 use crossterm::event;
 use crossterm::event::{poll, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
@@ -17,28 +27,80 @@ use std::collections::VecDeque;
 use std::mem;
 use std::ops::DerefMut;
 use std::sync::{Arc, LazyLock};
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
-use log::{error, info, warn};
-use tokio::sync::Mutex;
+use log::{error, info};
+use tokio::sync::{mpsc, Mutex};
 use tokio::time::Instant;
 
-#[derive(Default, Clone)]
-pub struct Queue {
-    player: Arc<Mutex<Player>>,
+/// fade out before the sleep timer stops playback
+const SLEEP_FADE: Duration = Duration::from_secs(30);
+/// fade out on stop and alarm snooze
+const STOP_FADE: Duration = Duration::from_secs(2);
+
+/// the live stream: fetching, the player and its controls; the fetching
+/// only needs a [`Buffer`] of the player
+#[derive(Default)]
+pub struct Queue<P = Player> {
+    player: Arc<Mutex<P>>,
     que: Arc<Mutex<VecDeque<Playlist>>>,
     api: Arc<Mutex<Api>>,
     ndt: Arc<std::sync::Mutex<NaiveDateTime>>,
     stat: Arc<Mutex<StateCollector>>,
+    link: Arc<Mutex<Connectivity>>,
+    now: Arc<std::sync::Mutex<NowPlaying>>,
     s1: Arc<HalfSleep>,
     s2: Arc<HalfSleep>,
     f1: bool,
+    /// a past program is playing, live fetching waits
+    timefree: Arc<AtomicBool>,
+}
+
+impl<P> Clone for Queue<P> {
+    fn clone(&self) -> Self {
+        Self {
+            player: Arc::clone(&self.player),
+            que: Arc::clone(&self.que),
+            api: Arc::clone(&self.api),
+            ndt: Arc::clone(&self.ndt),
+            stat: Arc::clone(&self.stat),
+            link: Arc::clone(&self.link),
+            now: Arc::clone(&self.now),
+            s1: Arc::clone(&self.s1),
+            s2: Arc::clone(&self.s2),
+            f1: self.f1,
+            timefree: Arc::clone(&self.timefree),
+        }
+    }
+}
+
+/// the buffer of the player as the fetching sees it
+pub trait Buffer {
+    fn buffer_length(&self) -> usize;
+    fn buffer_low(&self) -> bool;
+    fn buffer_clear(&mut self);
+}
+
+impl Buffer for Player {
+    fn buffer_length(&self) -> usize { Player::buffer_length(self) }
+    fn buffer_low(&self) -> bool { Player::buffer_low(self) }
+    fn buffer_clear(&mut self) { Player::buffer_clear(self) }
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct Playlist {
     url: String,
     buf: Vec<u8>,
+    duration: Duration,
+}
+
+/// state of the keyboard and remote controls
+struct Controls {
+    volume: char,
+    sleep: SleepTimer,
+    alarm: AlarmClock,
+    /// a past program playing in place of the live stream
+    catchup: Option<Timefree>,
 }
 
 impl Queue {
@@ -48,90 +110,97 @@ impl Queue {
         self.api.lock().await.init().await?;
         self.api.lock().await.inquire().await?;
         self.player.lock().await.buffer_clear();
+        self.set_station().await;
+        if !Options::init().alarm.is_empty() {
+            // silent until the first alarm
+            self.player.lock().await.stop(Duration::ZERO);
+        }
 
-        let mut _delay = Duration::from_secs(5);
+        *self.stat.lock().await = StateCollector::new(Options::init().latency.map(Duration::from_secs));
         let mut s = self.clone();
-        let stat = Arc::clone(&self.stat);
 
         tokio::spawn(async move {
+            let mut forbidden = false;
             loop {
-                let a = s.api.lock().await.medialist().await;
-                match a {
-                    Ok(urls) => {
-                        let instant = Instant::now();
-
-                        for url in urls {
-                            // TODO value: input contains invalid characters
-                            let mut stream_date = match naive_date_from(&url) {
-                                Ok(a) => a,
-                                Err(e) => {
-                                    error!("retry: {:?}\r", e);
-                                    naive_date_from(&url).unwrap()
-                                }
-                            };
-                            let last_date = s.ndt.lock().unwrap().to_owned();
-
-                            if last_date < stream_date {
-                                #[allow(unused_assignments)]
-                                let mut buf = Vec::new();
-
-                                loop {
-                                    buf = match s.api.lock().await.get_aac(&url).await {
-                                        Ok(buf) => {
-                                            if s.f1 {
-                                                s.player.lock().await.buffer_clear();
-                                                s.f1 = false;
-                                            }
-                                            buf
-                                        },
-                                        Err(_e) => {
-                                            error!("get_aac error: {:?}\r", _e);
-                                            warn!("retry {}\r", &url);
-                                            continue;
-                                        },
-                                    };
-                                    break;
-                                }
-
-                                s.que.lock().await.push_back(Playlist { url, buf });
-                                mem::swap(s.ndt.lock().unwrap().deref_mut(), &mut stream_date);
-                            }
-
-                            s.s2.wake();
-                        }
-                        _delay = s
-                            .api
-                            .lock()
-                            .await
-                            .duration(stat.lock().await.delay(), instant)
-                            .await;
-                    },
-                    Err(_) => {
-                        terminal::print_error(Error::from(Forbidden));
-                        let url =
-                            format!("{}{}", "forbidden", Local::now().format("_%Y%m%d_%H%M%S"));
-                        let len = s.player.lock().await.buffer_length();
-                        if len < 82920 {
-                            let p = Playlist {
-                                url,
-                                buf: ASSETS.get(rand()),
-                            };
-                            s.que.lock().await.push_back(p);
-                            s.f1 = true;
-                        }
-
-                        _delay = Duration::from_secs(30);
-                        s.s2.wake();
-                    },
-                };
-
-                s.s1.set(_delay).sleep().await;
+                if s.player.lock().await.is_stopped() || s.timefree.load(Ordering::Relaxed) {
+                    // sleeping until resumed
+                    s.que.lock().await.clear();
+                    s.s1.set(Duration::from_secs(3600)).sleep().await;
+                    continue;
+                }
+                let delay = s.fetch(&mut forbidden).await;
+                s.s1.set(delay).sleep().await;
             }
         });
 
-        let mut _current_volume = '9';
+        let (tx, mut rx) = mpsc::channel(16);
+        ipc::attach(Arc::clone(&self.now), tx);
+
+        let mut controls = Controls {
+            volume: '9',
+            sleep: SleepTimer::new(Options::init().sleep.unwrap_or_default()),
+            alarm: AlarmClock::new(Options::init().alarm),
+            catchup: None,
+        };
+        let mut starving = false;
+        let mut program_end = None;
+        let mut status = String::new();
+        let mut status_file = StatusFile::new(Options::init().status_file);
         enable_raw_mode()?;
         loop {
+            if let Ok(api) = self.api.try_lock() {
+                program_end = api.program_end();
+            }
+            match controls.alarm.poll(controls.volume) {
+                Some(AlarmEvent::Ring(station)) => {
+                    self.live(&mut controls).await;
+                    self.ring(station).await?
+                },
+                Some(AlarmEvent::Volume(c)) => self.player.lock().await.volume(c),
+                None => {},
+            }
+            let progress = match &mut controls.catchup {
+                Some(tf) => tf.step(&self.api, &self.player).await,
+                None => None,
+            };
+            if controls.catchup.is_some() && progress.is_none() {
+                // heard to the end
+                self.live(&mut controls).await;
+            }
+            let text = [
+                progress.unwrap_or_default(),
+                self.sleep_timer(&mut controls.sleep, program_end).await,
+                controls.alarm.status(),
+            ]
+            .join("  ")
+            .trim()
+            .to_string();
+            if text != status {
+                terminal::print_status(&text);
+                status = text;
+            }
+            let changed = self.now_playing(controls.volume).await;
+            status_file.update(&self.now.lock().unwrap(), changed);
+
+            {
+                let mut player = self.player.lock().await;
+                player.tick();
+                if player.starving() && !starving {
+                    let stats = player.stats();
+                    info!(
+                        "underrun {} corrupted {} resync {}\r",
+                        stats.underruns(),
+                        stats.corrupted_frames(),
+                        stats.resyncs()
+                    );
+                    terminal::print_warn("buffer underrun");
+                    // refetch now instead of waiting out the delay
+                    self.s1.wake();
+                }
+                starving = player.starving();
+            }
+
+            let mut requests = Vec::new();
             if poll(Duration::from_millis(200))? {
                 match event::read()? {
                     Event::Key(KeyEvent {
@@ -141,86 +210,548 @@ impl Queue {
                     }) => {
                         terminal::quit(Error::from(OperationInterrupted));
                     },
-                    Event::Key(e) => {
-                        if e.kind == KeyEventKind::Press {
-                            if let KeyCode::Char(c) = e.code {
-                                match c {
-                                    '0'..='9' => {
-                                        self.player.lock().await.volume(c);
-                                        _current_volume = c;
-                                    },
-                                    'n' | 'p' => {
-                                        mem::swap(
-                                            self.ndt.lock().unwrap().deref_mut(),
-                                            &mut NaiveDateTime::default(),
-                                        );
-
-                                        self.player.lock().await.buffer_clear();
-                                        match c {
-                                            'n' => self.api.lock().await.next_station().await?,
-                                            'p' => self.api.lock().await.prev_station().await?,
-                                            _ => {},
-                                        }
-                                        self.que.lock().await.clear();
-                                        self.s1.wake();
-                                        tokio::time::sleep(Duration::from_millis(100)).await;
-                                    },
-                                    'm' => {
-                                        self.api.lock().await.f1.swap(true, Ordering::Relaxed);
-                                        terminal::clear_screen();
-
-                                        {
-                                            let lock = self.api.lock().await;
-                                            println!("{} ({})\r",
-                                                     lock.current.area_name.as_ref().unwrap(),
-                                                     lock.current.area_id.as_ref().unwrap()
-                                            );
-                                        }
-
-                                        let stations = self.api.lock().await.get_stations();
-
-                                        let station = match menu::show(&stations) {
-                                            Ok(station) => {
-                                                let current_station = self
-                                                    .api
-                                                    .lock()
-                                                    .await
-                                                    .get_current_station()
-                                                    .unwrap();
-                                                if current_station == station {
-                                                    self.api.lock().await.f1.swap(false, Ordering::Relaxed);
-                                                    self.api.lock().await.current_prog().await?;
-                                                    continue;
-                                                }
-                                                station
-                                            },
-                                            Err(_) => {
-                                                self.api.lock().await.f1.swap(false, Ordering::Relaxed);
-                                                self.api.lock().await.current_prog().await?;
-                                                continue;
-                                            },
-                                        };
-                                        self.api.lock().await.select_station(station).await?;
-                                        self.player.lock().await.buffer_clear();
-                                        mem::swap(
-                                            self.ndt.lock().unwrap().deref_mut(),
-                                            &mut NaiveDateTime::default(),
-                                        );
-                                        self.que.lock().await.clear();
-                                        self.s1.wake();
-                                        self.api.lock().await.f1.swap(false, Ordering::Relaxed);
-                                        self.api.lock().await.current_prog().await?;
-                                    },
-                                    'i' => self.api.lock().await.current_prog().await?,
-                                    _ => {},
-                                }
+                    Event::Key(KeyEvent {
+                        code,
+                        kind: KeyEventKind::Press,
+                        ..
+                    }) if controls.catchup.is_some() => match Key::from_code(code) {
+                        // through dispatch, which keeps track of the volume
+                        Some(Key::Volume(c)) => requests.push(Request::from(Command::Volume(c))),
+                        Some(key) => {
+                            let tf = controls.catchup.as_mut().unwrap();
+                            if !tf.key(key, &self.player).await {
+                                self.live(&mut controls).await;
                             }
-                        }
+                        },
+                        None => {},
+                    },
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char(c),
+                        kind: KeyEventKind::Press,
+                        ..
+                    }) => {
+                        requests.extend(Command::from_key(c).map(Request::from));
                     },
                     _ => {},
                 }
             }
+            while let Ok(req) = rx.try_recv() {
+                requests.push(req);
+            }
+
+            for Request { cmd, reply } in requests {
+                info!("command {}\r", cmd);
+                let keeps_catchup = matches!(
+                    cmd,
+                    Command::Volume(_)
+                        | Command::Eq(_)
+                        | Command::Info
+                        | Command::Sleep
+                        | Command::Snooze
+                        | Command::AlarmStop
+                        | Command::Loudness
+                );
+                if !keeps_catchup {
+                    self.live(&mut controls).await;
+                }
+                let res = self.dispatch(cmd, &mut controls).await;
+                if let Ok(msg) = &res {
+                    if !msg.is_empty() {
+                        terminal::print_info(msg);
+                    }
+                }
+                match reply {
+                    Some(reply) => {
+                        let _ = reply.send(res.map_err(|e| e.to_string()));
+                    },
+                    None => {
+                        res?;
+                    },
+                }
+            }
+        }
+    }
+
+    /// carry out a command, returns a message for the user
+    async fn dispatch(&mut self, cmd: Command, c: &mut Controls) -> Result<String> {
+        let msg = match cmd {
+            Command::Volume(level) => {
+                c.alarm.cancel_ramp();
+                self.player.lock().await.volume(level);
+                c.volume = level;
+                String::new()
+            },
+            Command::Next | Command::Prev => {
+                mem::swap(
+                    self.ndt.lock().unwrap().deref_mut(),
+                    &mut NaiveDateTime::default(),
+                );
+
+                self.player.lock().await.transition();
+                match cmd {
+                    Command::Next => self.api.lock().await.next_station().await?,
+                    _ => self.api.lock().await.prev_station().await?,
+                }
+                self.set_station().await;
+                self.que.lock().await.clear();
+                self.s1.wake();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                self.api.lock().await.get_current_station().unwrap_or_default()
+            },
+            Command::Tune(key) => {
+                let name = self
+                    .api
+                    .lock()
+                    .await
+                    .find_station(&key)
+                    .ok_or(StationError)
+                    .with_context(|| format!("unknown station {}", key))?;
+                if self.api.lock().await.get_current_station() != Some(name.to_owned()) {
+                    self.tune(name.to_owned()).await?;
+                    self.api.lock().await.current_prog().await?;
+                }
+                name
+            },
+            Command::Menu => {
+                self.api.lock().await.f1.swap(true, Ordering::Relaxed);
+                terminal::clear_screen();
+
+                println!("{}\r", self.api.lock().await.area());
+
+                let (regions, current) = self.api.lock().await.regions();
+                let current_station = self.api.lock().await.get_current_station();
+
+                if let Ok(region) = menu::pick("region?", &regions, current) {
+                    let stations = self.api.lock().await.region_stations(region);
+                    if let Ok(station) = menu::show(&stations) {
+                        self.api.lock().await.set_region(region);
+                        if Some(&station) != current_station.as_ref() {
+                            self.tune(station).await?;
+                        }
+                    }
+                }
+                self.api.lock().await.f1.swap(false, Ordering::Relaxed);
+                self.api.lock().await.current_prog().await?;
+                String::new()
+            },
+            Command::Guide => {
+                self.api.lock().await.f1.swap(true, Ordering::Relaxed);
+                match self.guide().await {
+                    // the screen stays with the program until back to live
+                    Ok(Some(tf)) => c.catchup = Some(tf),
+                    res => {
+                        self.api.lock().await.f1.swap(false, Ordering::Relaxed);
+                        self.api.lock().await.current_prog().await?;
+                        res?;
+                    },
+                }
+                String::new()
+            },
+            Command::Info => {
+                self.api.lock().await.current_prog().await?;
+                String::new()
+            },
+            Command::Eq(preset) => {
+                let mut player = self.player.lock().await;
+                let preset = match preset {
+                    Some(preset) => player.set_eq(&preset)?,
+                    None => player.next_preset(),
+                };
+                format!("EQ {}", preset)
+            },
+            Command::Stop => {
+                self.player.lock().await.stop(STOP_FADE);
+                String::from("stopped")
+            },
+            Command::Play => self.resume().await,
+            Command::Sleep => {
+                if self.player.lock().await.is_stopped() {
+                    self.resume().await
+                } else {
+                    format!("sleep timer {}", c.sleep.cycle())
+                }
+            },
+            Command::Snooze => {
+                if !c.alarm.snooze() {
+                    return Ok(String::new());
+                }
+                self.player.lock().await.stop(STOP_FADE);
+                String::from("alarm snoozed")
+            },
+            Command::AlarmStop => {
+                if !c.alarm.stop() {
+                    return Ok(String::new());
+                }
+                let mut player = self.player.lock().await;
+                player.stop(STOP_FADE);
+                player.volume(c.volume);
+                String::from("alarm stopped")
+            },
+            Command::Loudness => {
+                let mut player = self.player.lock().await;
+                let enabled = player.toggle_loudness();
+                let (lufs, gain) = player.loudness();
+                format!(
+                    "loudness normalization {} ({} LUFS, {:+.1} dB)",
+                    if enabled { "on" } else { "off" },
+                    lufs.map_or("--".to_string(), |x| format!("{:.1}", x)),
+                    gain
+                )
+            },
+        };
+        Ok(msg)
+    }
+
+    /// switch to a station by name, crossfading from the current one
+    async fn tune(&mut self, name: String) -> Result<()> {
+        self.api.lock().await.select_station(name).await?;
+        self.player.lock().await.transition();
+        self.set_station().await;
+        mem::swap(
+            self.ndt.lock().unwrap().deref_mut(),
+            &mut NaiveDateTime::default(),
+        );
+        self.que.lock().await.clear();
+        self.s1.wake();
+        Ok(())
+    }
+
+    /// pick an earlier program of today and start playing it, the main loop
+    /// carries on with it
+    async fn guide(&mut self) -> Result<Option<Timefree>> {
+        let (id, station) = {
+            let api = self.api.lock().await;
+            let id = api.get_current_station_id().ok_or(StationError)?;
+            (id, api.get_current_station().unwrap_or_default())
+        };
+        let now = jst_now().format("%Y%m%d%H%M%S").to_string();
+        let progs = guide::day(&mut *self.api.lock().await, &id, broadcast_date())
+            .await?
+            .into_iter()
+            .filter(|x| x.ft < now)
+            .collect::<Vec<_>>();
+        let v = progs
+            .iter()
+            .map(|x| {
+                let time = |x: &str| x.get(8..12).map(|x| format!("{}:{}", &x[..2], &x[2..]));
+                format!(
+                    "{}-{} {}",
+                    time(&x.ft).unwrap_or_default(),
+                    time(&x.to).unwrap_or_default(),
+                    x.title
+                )
+            })
+            .collect::<Vec<_>>();
+        if v.is_empty() {
+            return Ok(None);
+        }
+
+        terminal::clear_screen();
+        println!("{}\r", station);
+        let Ok(i) = menu::pick("program?", &v, v.len() - 1) else {
+            return Ok(None);
+        };
+        let tf = Timefree::new(&id, progs[i].to_owned())?;
+
+        self.timefree.store(true, Ordering::Relaxed);
+        self.que.lock().await.clear();
+        {
+            let mut player = self.player.lock().await;
+            if player.is_stopped() {
+                player.resume();
+            }
+            player.buffer_clear();
+        }
+        tf.header(&station);
+        Ok(Some(tf))
+    }
+
+    /// leave catch-up for the live edge
+    async fn live(&mut self, c: &mut Controls) {
+        if c.catchup.take().is_none() {
+            return;
+        }
+        terminal::print_status("");
+        {
+            let mut player = self.player.lock().await;
+            player.pause(false);
+            player.buffer_clear();
+        }
+        self.timefree.store(false, Ordering::Relaxed);
+        *self.ndt.lock().unwrap() = NaiveDateTime::default();
+        self.s1.wake();
+        let mut api = self.api.lock().await;
+        api.f1.swap(false, Ordering::Relaxed);
+        if let Err(e) = api.current_prog().await {
+            error!("current_prog: {:?}\r", e);
+        }
+    }
+
+    /// resume after stop, at the live edge
+    async fn resume(&mut self) -> String {
+        let mut player = self.player.lock().await;
+        if !player.is_stopped() {
+            return String::new();
         }
+        player.resume();
+        *self.ndt.lock().unwrap() = NaiveDateTime::default();
+        self.s1.wake();
+        String::from("resume")
+    }
+    /// fade out and stop once the sleep timer runs out, returns the status text
+    async fn sleep_timer(
+        &self,
+        sleep: &mut SleepTimer,
+        program_end: Option<NaiveDateTime>,
+    ) -> String {
+        if sleep.is_off() {
+            return String::new();
+        }
+        let mut player = self.player.lock().await;
+        let latency = self.stat.lock().await.latency(player.buffer_length());
+        let Some(remaining) = sleep.remaining(program_end, latency) else {
+            return String::from("sleep at end of program");
+        };
+        if remaining <= SLEEP_FADE {
+            player.stop(remaining);
+            sleep.set(Timer::Off);
+            terminal::print_info("sleep timer: stopped, press s to resume");
+            return String::new();
+        }
+        let secs = remaining.as_secs();
+        format!("sleep in {}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+
+    /// refresh the now playing snapshot, true if it changed
+    async fn now_playing(&self, volume: char) -> bool {
+        let mut now = self.now.lock().unwrap().clone();
+        if let Ok(api) = self.api.try_lock() {
+            (now.station, now.station_id) = (api.get_current_station(), api.get_current_station_id());
+            let time = |x: &str| {
+                NaiveDateTime::parse_from_str(x, "%Y%m%d%H%M%S")
+                    .map(|x| x.format("%Y-%m-%dT%H:%M:%S").to_string())
+                    .ok()
+            };
+            now.title = api.program().map(|x| x.title.to_owned());
+            now.start = api.program().and_then(|x| time(&x.ft));
+            now.end = api.program().and_then(|x| time(&x.to));
+        }
+        {
+            let player = self.player.lock().await;
+            let stat = self.stat.lock().await;
+            let len = player.buffer_length();
+            let buffered = stat.buffered(len);
+            now.buffer_bytes = len;
+            now.buffer_secs = buffered.as_secs_f32();
+            now.buffer_fill = buffered.as_secs_f32() / stat.buffer_target().as_secs_f32().max(1.0);
+            now.stopped = player.is_stopped();
+        }
+        now.volume = volume.to_digit(10).unwrap_or_default() as u8;
+        now.connection = self.link.lock().await.state.to_string();
+
+        let mut prev = self.now.lock().unwrap();
+        let changed = *prev != now;
+        *prev = now;
+        changed
+    }
+
+    /// tune in for an alarm, the volume ramps up from silence
+    async fn ring(&mut self, station: Option<String>) -> Result<()> {
+        terminal::print_info("alarm");
+        self.player.lock().await.volume('0');
+
+        let online = self.link.lock().await.state == Link::Online;
+        let name = match &station {
+            Some(x) => {
+                let name = self.api.lock().await.find_station(x);
+                if name.is_none() {
+                    terminal::print_warn(format!("unknown station {}", x));
+                }
+                name
+            },
+            None => None,
+        };
+        let current = self.api.lock().await.get_current_station();
+        match name {
+            Some(name) if online && Some(&name) != current.as_ref() => {
+                self.api.lock().await.select_station(name).await?;
+                self.player.lock().await.transition();
+                self.set_station().await;
+                self.que.lock().await.clear();
+            },
+            _ => {
+                let mut player = self.player.lock().await;
+                if player.is_stopped() {
+                    player.resume();
+                }
+            },
+        }
+        *self.ndt.lock().unwrap() = NaiveDateTime::default();
+
+        if !online {
+            // wake up to something rather than silence
+            let stat = Arc::clone(&self.stat);
+            self.filler(&stat).await;
+        }
+        self.s1.wake();
+        Ok(())
+    }
+
+    /// tell the player which station it is about to receive
+    async fn set_station(&self) {
+        if let Some(id) = self.api.lock().await.get_current_station_id() {
+            self.player.lock().await.set_station(&id);
+        }
+    }
+}
+
+impl<P: Buffer> Queue<P> {
+    /// one round of live fetching: the playlist, then the segments not
+    /// queued yet; returns how long to wait before the next
+    async fn fetch(&mut self, forbidden: &mut bool) -> Duration {
+        let stat = Arc::clone(&self.stat);
+        let mut _delay;
+        let a = self.api.lock().await.medialist().await;
+        match a {
+            Ok(playlist) => {
+                let instant = Instant::now();
+                let target_duration = playlist.target_duration;
+                stat.lock().await.set_target(target_duration);
+                let mut skip = 0;
+                *forbidden = false;
+                if self.link.lock().await.state == Link::Offline {
+                    // resume at the live edge
+                    let target = stat.lock().await.buffer_target();
+                    let n = target.as_millis() / playlist.target_duration.as_millis().max(1);
+                    skip = playlist.segments.len().saturating_sub(n as usize + 1);
+                    *self.ndt.lock().unwrap() = NaiveDateTime::default();
+                }
+                self.online(Fetch::Playlist).await;
+
+                let map = playlist.map;
+                for segment in playlist.segments.into_iter().skip(skip) {
+                    let (url, duration) = (segment.url, segment.duration);
+                    let mut stream_date = match naive_date_from(&url) {
+                        Ok(a) => a,
+                        Err(e) => {
+                            error!("skip {}: {:?}\r", url, e);
+                            continue;
+                        },
+                    };
+                    let last_date = self.ndt.lock().unwrap().to_owned();
+
+                    if self.timefree.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if last_date < stream_date {
+                        let res = self.api.lock().await.get_segment(&url, map.as_deref()).await;
+                        let buf = match res {
+                            Ok(buf) => {
+                                self.online(Fetch::Segment).await;
+                                if self.f1 {
+                                    self.player.lock().await.buffer_clear();
+                                    self.f1 = false;
+                                }
+                                buf
+                            },
+                            Err(e) => {
+                                error!("get_aac error: {:?}\r", e);
+                                if network_error(&e) {
+                                    self.offline(Fetch::Segment, &stat).await;
+                                }
+                                break;
+                            },
+                        };
+
+                        self.que.lock().await.push_back(Playlist { url, buf, duration });
+                        mem::swap(self.ndt.lock().unwrap().deref_mut(), &mut stream_date);
+                    }
+
+                    self.s2.wake();
+                }
+
+                let backoff = self.link.lock().await.backoff();
+                if !backoff.is_zero() {
+                    _delay = backoff;
+                } else {
+                    let len = self.player.lock().await.buffer_length();
+                    let (ave, delay) = {
+                        let stat = stat.lock().await;
+                        (stat.latency(len), stat.delay(len))
+                    };
+                    _delay = self.api.lock().await.duration(ave, delay, instant).await;
+                }
+                if self.player.lock().await.buffer_low() {
+                    _delay = _delay.min(target_duration / 2);
+                }
+            },
+            Err(e) if network_error(&e) => {
+                error!("medialist error: {:?}\r", e);
+                self.offline(Fetch::Playlist, &stat).await;
+                _delay = self.link.lock().await.backoff();
+            },
+            Err(_) => {
+                terminal::print_error(Error::from(Forbidden));
+                if !*forbidden {
+                    hooks::emit(self.api.lock().await.event(Kind::Forbidden));
+                    *forbidden = true;
+                }
+                self.filler(&stat).await;
+                _delay = Duration::from_secs(30);
+            },
+        };
+
+        _delay
+    }
+
+    /// record a successful fetch, telling of the link coming back
+    async fn online(&self, fetch: Fetch) {
+        let mut link = self.link.lock().await;
+        let downtime = link.elapsed();
+        let Some(prev) = link.success(fetch) else {
+            return;
+        };
+        terminal::print_warn(format!("network {} -> {}", prev, link.state));
+        if prev == Link::Offline {
+            drop(link);
+            let mut event = self.api.lock().await.event(Kind::Reconnect);
+            event.downtime = Some(downtime.as_secs());
+            hooks::emit(event);
+        }
+    }
+
+    /// record a network failure, falling back on the filler once the buffer runs low
+    async fn offline(&mut self, fetch: Fetch, stat: &Mutex<StateCollector>) {
+        let mut link = self.link.lock().await;
+        if let Some(prev) = link.failure(fetch) {
+            terminal::print_warn(format!(
+                "network {} -> {} after {:?}",
+                prev,
+                link.state,
+                link.elapsed()
+            ));
+        }
+        if link.state == Link::Offline {
+            drop(link);
+            self.filler(stat).await;
+        }
+    }
+
+    /// queue the embedded filler audio while the buffer is under target
+    async fn filler(&mut self, stat: &Mutex<StateCollector>) {
+        let url = format!("{}{}", "forbidden", Local::now().format("_%Y%m%d_%H%M%S"));
+        let len = self.player.lock().await.buffer_length();
+        let stat = stat.lock().await;
+        if stat.buffered(len) < stat.buffer_target() {
+            let p = Playlist {
+                url,
+                buf: ASSETS.get(rand()),
+                ..Default::default()
+            };
+            self.que.lock().await.push_back(p);
+            self.f1 = true;
+        }
+        self.s2.wake();
     }
 }
 
@@ -229,25 +760,33 @@ pub async fn player(medialist: Queue) -> Result<()> {
     tokio::spawn(async move {
         loop {
             let len = s.que.lock().await.len();
-            if len > 0 {
-                let p = s.que.lock().await.pop_front().unwrap();
+            loop {
+                if s.player.lock().await.buffer_full() {
+                    break;
+                }
+                let p = match s.que.lock().await.pop_front() {
+                    Some(p) => p,
+                    None => break,
+                };
                 s.player.lock().await.add(&p.buf);
 
-                let ndt = naive_date_from(&p.url).unwrap();
-                let blen = s.player.lock().await.buffer_length() as i64;
-                s.stat
-                    .lock()
-                    .await
-                    .add(blen, (Local::now().naive_local() - ndt).num_milliseconds());
-                info!(
-                    "Add {:?} {} {} bytes\r",
-                    p.url,
-                    len,
-                    s.player.lock().await.buffer_length(),
-                );
+                let ndt = naive_date_from(&p.url).unwrap_or(jst_now());
+                let (blen, dropped) = {
+                    let player = s.player.lock().await;
+                    (player.buffer_length(), player.buffer_dropped())
+                };
+                if !p.duration.is_zero() {
+                    s.stat.lock().await.add(
+                        p.buf.len() as i64,
+                        p.duration.as_millis() as i64,
+                        (jst_now() - ndt).num_milliseconds(),
+                    );
+                }
+                info!("Add {:?} {} {} bytes ({} dropped)\r", p.url, len, blen, dropped);
             }
 
-            s.s2.set(Duration::from_secs(5)).sleep().await;
+            let target = s.stat.lock().await.buffer_target();
+            s.s2.set(target).sleep().await;
         }
     });
 
@@ -264,8 +803,187 @@ lazy_regex!(
     RE: r".*(\d{8})_(\d{6}).*"
 );
 
-fn naive_date_from(url: &str) -> Result<NaiveDateTime> {
+pub(crate) fn naive_date_from(url: &str) -> Result<NaiveDateTime> {
     let date = RE.replace(url, |caps: &Captures| format!("{}{}", &caps[1], &caps[2]));
     let ndt = NaiveDateTime::parse_from_str(&date, "%Y%m%d%H%M%S")?;
     Ok(ndt)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::retry::RetryPolicy;
+    use crate::api::{Data, State, Url};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Mutex as StdMutex;
+    use std::thread;
+
+    const SEGMENT: usize = 1000;
+
+    /// the player buffer, filled and drained by the test
+    #[derive(Default)]
+    struct Held {
+        len: usize,
+        cleared: usize,
+    }
+
+    impl Buffer for Held {
+        fn buffer_length(&self) -> usize { self.len }
+        fn buffer_low(&self) -> bool { self.len < SEGMENT }
+        fn buffer_clear(&mut self) {
+            self.len = 0;
+            self.cleared += 1;
+        }
+    }
+
+    /// a stand-in for the stream server: the playlist of `segments` and
+    /// their audio, or hanging up on everything while down
+    struct Server {
+        addr: SocketAddr,
+        up: Arc<AtomicBool>,
+        segments: Arc<StdMutex<Vec<String>>>,
+    }
+
+    impl Server {
+        fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let server = Server {
+                addr: listener.local_addr().unwrap(),
+                up: Arc::new(AtomicBool::new(true)),
+                segments: Default::default(),
+            };
+            let (up, segments) = (Arc::clone(&server.up), Arc::clone(&server.segments));
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    if !up.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    let mut buf = [0; 1024];
+                    let n = stream.read(&mut buf).unwrap_or_default();
+                    let req = String::from_utf8_lossy(&buf[..n]);
+                    let body = match req.split(' ').nth(1) {
+                        Some("/live.m3u8") => segments
+                            .lock()
+                            .unwrap()
+                            .iter()
+                            .fold("#EXTM3U\n#EXT-X-TARGETDURATION:5\n".to_string(), |acc, x| {
+                                acc + &format!("#EXTINF:5,\n{}\n", x)
+                            })
+                            .into_bytes(),
+                        _ => vec![0; SEGMENT],
+                    };
+                    let _ = stream.write_all(format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).as_bytes());
+                    let _ = stream.write_all(&body);
+                }
+            });
+            server
+        }
+
+        /// the live edge moves on by `n` segments of 5 s
+        fn advance(&self, n: usize) {
+            let mut segments = self.segments.lock().unwrap();
+            let start = NaiveDateTime::parse_from_str("20261019120000", "%Y%m%d%H%M%S").unwrap();
+            for _ in 0..n {
+                let at = start + chrono::TimeDelta::seconds(5 * segments.len() as i64);
+                segments.push(format!("seg/{}.aac", at.format("%Y%m%d_%H%M%S")));
+            }
+        }
+
+        fn url(&self, segment: &str) -> String {
+            format!("http://{}/{}", self.addr, segment)
+        }
+    }
+
+    fn queue(server: &Server) -> Queue<Held> {
+        let api = Api {
+            client: reqwest::Client::builder().pool_max_idle_per_host(0).build().unwrap(),
+            url: Url {
+                station: Some(server.url("live.m3u8")),
+                ..Default::default()
+            },
+            param: Default::default(),
+            data: Data { region: Default::default() },
+            current: State {
+                to: jst_now() + chrono::TimeDelta::hours(1),
+                ..Default::default()
+            },
+            retry: RetryPolicy {
+                max_attempts: 1,
+                base: Duration::ZERO,
+                max: Duration::ZERO,
+            },
+            f1: Default::default(),
+        };
+        Queue {
+            player: Default::default(),
+            que: Default::default(),
+            api: Arc::new(Mutex::new(api)),
+            ndt: Default::default(),
+            stat: Default::default(),
+            link: Default::default(),
+            now: Default::default(),
+            s1: Default::default(),
+            s2: Default::default(),
+            f1: false,
+            timefree: Default::default(),
+        }
+    }
+
+    /// what the player task does: the queue goes into the buffer
+    async fn play(q: &Queue<Held>) -> Vec<String> {
+        let mut urls = Vec::new();
+        while let Some(p) = q.que.lock().await.pop_front() {
+            q.player.lock().await.len += p.buf.len();
+            if !p.duration.is_zero() {
+                q.stat.lock().await.add(p.buf.len() as i64, p.duration.as_millis() as i64, 0);
+            }
+            urls.push(p.url);
+        }
+        urls
+    }
+
+    #[tokio::test]
+    async fn outage() {
+        let server = Server::start();
+        server.advance(6);
+        let mut q = queue(&server);
+        let mut forbidden = false;
+
+        // everything on the playlist, then only what is new
+        q.fetch(&mut forbidden).await;
+        assert_eq!(play(&q).await.len(), 6);
+        server.advance(1);
+        q.fetch(&mut forbidden).await;
+        assert_eq!(play(&q).await, [server.url("seg/20261019_120030.aac")]);
+
+        // the server goes away; three failures take the link down, 35 s of
+        // buffered audio ride them out without the filler
+        server.up.store(false, Ordering::Relaxed);
+        for _ in 0..3 {
+            let delay = q.fetch(&mut forbidden).await;
+            assert!(delay > Duration::ZERO);
+        }
+        assert_eq!(q.link.lock().await.state, Link::Offline);
+        assert!(play(&q).await.is_empty());
+
+        // once the buffer runs low, the filler plays
+        q.player.lock().await.len = SEGMENT;
+        q.fetch(&mut forbidden).await;
+        let urls = play(&q).await;
+        assert!(urls.len() == 1 && urls[0].starts_with("forbidden"), "{:?}", urls);
+
+        // back: the filler makes way for the live edge, 15 s behind it
+        // instead of where the stream stopped
+        server.advance(6);
+        server.up.store(true, Ordering::Relaxed);
+        q.fetch(&mut forbidden).await;
+        assert_eq!(q.link.lock().await.state, Link::Online);
+        assert_eq!(q.player.lock().await.cleared, 1);
+        let urls = play(&q).await;
+        let edge = server.segments.lock().unwrap().iter().rev().take(4).rev().map(|x| server.url(x)).collect::<Vec<_>>();
+        assert_eq!(urls, edge);
+        assert!(!forbidden);
+    }
+}
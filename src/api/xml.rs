@@ -1,4 +1,52 @@
-use serde::{Deserialize, Serialize};
+use serde::de::{Error, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+struct Lenient<T>(PhantomData<T>);
+
+impl<'de, T: FromStr + Default> Visitor<'de> for Lenient<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number")
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<T, E> {
+        Ok(v.trim().parse().unwrap_or_default())
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<T, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<T, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<T, E> {
+        Ok(T::default())
+    }
+
+    /// an element, its text being `$value`
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut value = T::default();
+        while let Some((k, v)) = map.next_entry::<String, String>()? {
+            if k == "$value" {
+                value = self.visit_str::<A::Error>(&v)?;
+                break;
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// a number that may be empty or malformed, which should not fail the rest;
+/// text in the XML, a number once saved as JSON
+fn lenient<'de, D: Deserializer<'de>, T: FromStr + Default>(d: D) -> Result<T, D::Error> {
+    d.deserialize_any(Lenient(PhantomData))
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Region {
@@ -6,17 +54,67 @@ pub struct Region {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
 pub struct Stations {
     pub station: Vec<Station>,
     pub region_id: String,
     pub region_name: String,
+    pub ascii_name: String,
 }
 
+/// a station of the region list, every field but the id and name optional
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Station {
+    /// required
     pub id: String,
     pub name: String,
+    #[serde(default)]
     pub area_id: String,
+    #[serde(default)]
+    pub ascii_name: String,
+    /// reading in hiragana
+    #[serde(default)]
+    pub ruby: String,
+    /// 1 if it can be heard outside its area
+    #[serde(default, deserialize_with = "lenient")]
+    pub areafree: u8,
+    /// 1 if past programs can be played
+    #[serde(default, deserialize_with = "lenient")]
+    pub timefree: u8,
+    #[serde(default)]
+    pub logo: Vec<Logo>,
+    #[serde(default)]
+    pub banner: String,
+    #[serde(default)]
+    pub href: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub simul_max_delay: u32,
+    /// hours past programs stay available
+    #[serde(default, deserialize_with = "lenient")]
+    pub tf_max_delay: u32,
+}
+
+impl Station {
+    /// url of the largest logo
+    pub fn logo_url(&self) -> Option<&str> {
+        self.logo
+            .iter()
+            .max_by_key(|x| x.width * x.height)
+            .map(|x| x.url.as_str())
+            .filter(|x| !x.is_empty())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Logo {
+    #[serde(deserialize_with = "lenient")]
+    pub width: u32,
+    #[serde(deserialize_with = "lenient")]
+    pub height: u32,
+    pub align: String,
+    #[serde(rename = "$value")]
+    pub url: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -39,20 +137,219 @@ pub struct CurrentProg {
 pub struct PStations {
     pub station: PStation,
 }
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
 pub struct PStation {
+    pub id: String,
+    pub name: String,
     pub progs: Progs,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
 pub struct Progs {
+    /// broadcast date, YYYYMMDD
+    pub date: String,
     pub prog: Vec<Prog>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// a program of the guide, every field but the times optional
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Prog {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub master_id: String,
+    /// YYYYMMDDhhmmss, required
     pub ft: String,
     pub to: String,
+    /// hhmm, past 24 after midnight
+    #[serde(default)]
+    pub ftl: String,
+    #[serde(default)]
+    pub tol: String,
+    /// seconds
+    #[serde(default, deserialize_with = "lenient")]
+    pub dur: u32,
+    #[serde(default)]
     pub title: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub failed_record: u8,
+    #[serde(default, deserialize_with = "lenient")]
+    pub ts_in_ng: u8,
+    #[serde(default, deserialize_with = "lenient")]
+    pub ts_out_ng: u8,
+    /// HTML
+    #[serde(default)]
+    pub desc: String,
+    /// HTML
+    #[serde(default)]
     pub info: String,
+    /// performers
+    #[serde(default)]
+    pub pfm: String,
+    #[serde(default)]
+    pub img: String,
+    #[serde(default)]
+    pub tag: ProgTags,
+    #[serde(default)]
+    pub genre: Genre,
+    #[serde(default)]
+    pub metas: Metas,
+}
+
+impl Prog {
+    /// tag names
+    pub fn tags(&self) -> Vec<&str> {
+        self.tag.item.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    /// genre names, program first
+    pub fn genres(&self) -> Vec<&str> {
+        [&self.genre.program, &self.genre.personality]
+            .into_iter()
+            .flatten()
+            .map(|x| x.name.as_str())
+            .filter(|x| !x.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ProgTags {
+    pub item: Vec<Named>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Named {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Genre {
+    pub personality: Option<Named>,
+    pub program: Option<Named>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Metas {
+    pub meta: Vec<Meta>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Meta {
+    pub name: String,
+    pub value: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_xml_rs::from_str;
+
+    const REGION: &str = include_str!("../../tests/fixtures/region_full.xml");
+    const PROGRAM: &str = include_str!("../../tests/fixtures/program_TBS_20261017.xml");
+
+    #[test]
+    fn region() {
+        let region: Region = from_str(REGION).unwrap();
+        assert_eq!(region.stations.len(), 2);
+        let hokkaido = &region.stations[0];
+        assert_eq!(hokkaido.region_id, "hokkaido-tohoku");
+        assert_eq!(hokkaido.ascii_name, "HOKKAIDO-TOHOKU");
+
+        let hbc = &hokkaido.station[0];
+        assert_eq!((hbc.id.as_str(), hbc.area_id.as_str()), ("HBC", "JP1"));
+        assert_eq!(hbc.ruby, "えいちびーしーらじお");
+        assert_eq!((hbc.areafree, hbc.timefree, hbc.tf_max_delay), (1, 1, 15));
+        assert_eq!(hbc.logo.len(), 3);
+        assert_eq!((hbc.logo[0].width, hbc.logo[0].height), (124, 40));
+        assert_eq!(
+            hbc.logo_url(),
+            Some("https://radiko.jp/v2/static/station/logo/HBC/448x200.png")
+        );
+
+        // empty, malformed and missing elements
+        let stv = &hokkaido.station[1];
+        assert_eq!(stv.name, "STVラジオ");
+        assert_eq!((stv.areafree, stv.timefree, stv.tf_max_delay), (0, 0, 0));
+        assert!(stv.logo.is_empty());
+        assert_eq!(stv.logo_url(), None);
+
+        let tbs = &region.stations[1].station[0];
+        assert_eq!((tbs.id.as_str(), tbs.href.as_str()), ("TBS", "https://www.tbsradio.jp/"));
+    }
+
+    #[test]
+    fn program() {
+        let current: CurrentProg = from_str(PROGRAM).unwrap();
+        let station = &current.stations.station;
+        assert_eq!((station.id.as_str(), station.name.as_str()), ("TBS", "TBSラジオ"));
+        assert_eq!(station.progs.date, "20261017");
+        let progs = &station.progs.prog;
+        assert_eq!(progs.len(), 3);
+
+        let prog = &progs[0];
+        assert_eq!((prog.ft.as_str(), prog.to.as_str()), ("20261017050000", "20261017053000"));
+        assert_eq!((prog.ftl.as_str(), prog.tol.as_str(), prog.dur), ("0500", "0530", 1800));
+        assert_eq!(prog.pfm, "森本毅郎、遠藤泰子");
+        assert_eq!(prog.url, "https://www.tbsradio.jp/stand-by/");
+        assert_eq!(prog.info, "<b>メール</b><br />stand-by@tbs.co.jp");
+        assert_eq!(prog.desc, "<p>朝の情報番組</p>");
+        assert_eq!(prog.tags(), ["ニュース", "生放送"]);
+        assert_eq!(prog.genres(), ["ニュース/天気/交通", "アナウンサー"]);
+        assert_eq!(prog.genre.program.as_ref().unwrap().id, "P001");
+        assert_eq!(prog.metas.meta.len(), 2);
+        assert_eq!(prog.metas.meta[0].value, "#standby954");
+
+        // only the times and a title, and an empty duration
+        let prog = &progs[1];
+        assert_eq!(prog.title, "短い番組");
+        assert_eq!(prog.dur, 0);
+        assert!(prog.pfm.is_empty() && prog.tags().is_empty() && prog.genres().is_empty());
+
+        // past midnight, with an element we do not know
+        let prog = &progs[2];
+        assert_eq!((prog.ftl.as_str(), prog.tol.as_str()), ("2830", "2900"));
+        assert_eq!(prog.genres(), ["バラエティ"]);
+    }
+
+    #[test]
+    fn program_without_times() {
+        let xml = PROGRAM.replacen(r#"ft="20261017050000" "#, "", 1);
+        assert!(from_str::<CurrentProg>(&xml).is_err());
+    }
+
+    #[test]
+    fn station_without_id() {
+        let xml = REGION.replacen("<id>STV</id>", "", 1);
+        assert!(from_str::<Region>(&xml).is_err());
+        let xml = REGION.replacen("<name>STVラジオ</name>", "", 1);
+        assert!(from_str::<Region>(&xml).is_err());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let current: CurrentProg = from_str(PROGRAM).unwrap();
+        let json = serde_json::to_string(&current).unwrap();
+        let back: CurrentProg = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+        assert_eq!(back.stations.station.progs.prog[0].dur, 1800);
+        assert_eq!(back.stations.station.progs.prog[0].genres(), ["ニュース/天気/交通", "アナウンサー"]);
+
+        let region: Region = from_str(REGION).unwrap();
+        let json = serde_json::to_string(&region).unwrap();
+        let back: Region = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+        assert_eq!(back.stations[0].station[0].logo_url(), region.stations[0].station[0].logo_url());
+        assert_eq!(back.stations[0].station[0].tf_max_delay, 15);
+    }
 }
@@ -0,0 +1,383 @@
+use crate::audio::filter::Biquad;
+use crate::errors::RadicoError::EqPreset;
+use anyhow::{Error, Result};
+use rodio::Source;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// presets cycled by the `e` key
+pub const PRESETS: [&str; 6] = ["flat", "bass", "laptop", "talk", "night", "mono"];
+/// centre frequencies of a custom 10 band graphic EQ
+const GRAPHIC: [f64; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+const GRAPHIC_Q: f64 = 1.41;
+const SHELF_Q: f64 = 0.707;
+
+/// compressor tuned for speech
+const THRESHOLD_DB: f64 = -24.0;
+const RATIO: f64 = 4.0;
+const MAKEUP_DB: f64 = 8.0;
+const ATTACK_SEC: f64 = 0.010;
+const RELEASE_SEC: f64 = 0.200;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Band {
+    Peak { freq: f64, q: f64, gain: f64 },
+    LowShelf { freq: f64, gain: f64 },
+    HighShelf { freq: f64, gain: f64 },
+    HighPass { freq: f64 },
+}
+
+impl Band {
+    /// None when the band is beyond Nyquist
+    fn filter(&self, rate: u32) -> Option<Biquad> {
+        let (freq, filter) = match *self {
+            Band::Peak { freq, q, gain } => (freq, Biquad::peaking(rate, freq, q, gain)),
+            Band::LowShelf { freq, gain } => (freq, Biquad::low_shelf(rate, freq, SHELF_Q, gain)),
+            Band::HighShelf { freq, gain } => (freq, Biquad::high_shelf(rate, freq, SHELF_Q, gain)),
+            Band::HighPass { freq } => (freq, Biquad::high_pass(rate, freq, SHELF_Q)),
+        };
+        (freq < rate as f64 / 2.0).then_some(filter)
+    }
+
+    fn gain(&self) -> f64 {
+        match *self {
+            Band::Peak { gain, .. } | Band::LowShelf { gain, .. } | Band::HighShelf { gain, .. } => gain,
+            Band::HighPass { .. } => 0.0,
+        }
+    }
+}
+
+/// effects chain: EQ bands, then mono downmix, then compressor
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub name: String,
+    pub bands: Vec<Band>,
+    pub compressor: bool,
+    pub mono: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            name: PRESETS[0].to_string(),
+            bands: Vec::new(),
+            compressor: false,
+            mono: false,
+        }
+    }
+}
+
+impl Settings {
+    fn is_flat(&self) -> bool {
+        self.bands.is_empty() && !self.compressor && !self.mono
+    }
+
+    /// headroom for the largest boost
+    fn preamp(&self) -> f64 {
+        let boost = self.bands.iter().map(Band::gain).fold(0.0, f64::max);
+        10_f64.powf(-boost / 20.0)
+    }
+
+    /// the preset after this one
+    pub fn next(&self) -> Settings {
+        let i = PRESETS.iter().position(|x| *x == self.name).map_or(0, |x| x + 1);
+        PRESETS[i % PRESETS.len()].parse().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl FromStr for Settings {
+    type Err = Error;
+
+    /// a preset name, or ten comma separated band gains in dB
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.to_string();
+        let settings = match s {
+            "flat" => Settings::default(),
+            "bass" => Settings {
+                name,
+                bands: vec![Band::LowShelf { freq: 100.0, gain: 6.0 }],
+                ..Default::default()
+            },
+            "laptop" => Settings {
+                name,
+                bands: vec![
+                    Band::HighPass { freq: 120.0 },
+                    Band::Peak { freq: 250.0, q: 1.0, gain: -3.0 },
+                    Band::Peak { freq: 3000.0, q: 1.0, gain: 3.0 },
+                    Band::HighShelf { freq: 8000.0, gain: 2.0 },
+                ],
+                ..Default::default()
+            },
+            "talk" => Settings {
+                name,
+                bands: vec![
+                    Band::HighPass { freq: 80.0 },
+                    Band::Peak { freq: 2500.0, q: 0.8, gain: 4.0 },
+                ],
+                compressor: true,
+                ..Default::default()
+            },
+            "night" => Settings {
+                name,
+                bands: vec![Band::LowShelf { freq: 100.0, gain: -3.0 }],
+                compressor: true,
+                ..Default::default()
+            },
+            "mono" => Settings {
+                name,
+                mono: true,
+                ..Default::default()
+            },
+            _ => {
+                let gains = s
+                    .split(',')
+                    .map(|x| x.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| EqPreset(name.to_owned()))?;
+                if gains.len() != GRAPHIC.len() {
+                    return Err(Error::from(EqPreset(name)));
+                }
+                Settings {
+                    name: "custom".to_string(),
+                    bands: GRAPHIC
+                        .iter()
+                        .zip(gains)
+                        .filter(|(_, gain)| *gain != 0.0)
+                        .map(|(&freq, gain)| Band::Peak {
+                            freq,
+                            q: GRAPHIC_Q,
+                            gain: gain.clamp(-12.0, 12.0),
+                        })
+                        .collect(),
+                    ..Default::default()
+                }
+            },
+        };
+        Ok(settings)
+    }
+}
+
+/// effects settings shared by the player and every channel
+#[derive(Debug, Default)]
+pub struct Dsp {
+    settings: Mutex<Settings>,
+    version: AtomicU32,
+}
+
+impl Dsp {
+    pub fn new(settings: Settings) -> Self {
+        Dsp {
+            settings: Mutex::new(settings),
+            version: AtomicU32::new(0),
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, settings: Settings) {
+        *self.settings.lock().unwrap() = settings;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// feed-forward peak compressor, linked across channels
+#[derive(Debug, Default)]
+struct Compressor {
+    envelope: f64,
+    attack: f64,
+    release: f64,
+}
+
+impl Compressor {
+    fn new(rate: u32) -> Self {
+        Compressor {
+            envelope: 0.0,
+            attack: (-1.0 / (ATTACK_SEC * rate as f64)).exp(),
+            release: (-1.0 / (RELEASE_SEC * rate as f64)).exp(),
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f64]) {
+        let peak = frame.iter().fold(0.0, |a: f64, x| a.max(x.abs()));
+        let coef = if peak > self.envelope { self.attack } else { self.release };
+        self.envelope = coef * self.envelope + (1.0 - coef) * peak;
+
+        let over = 20.0 * self.envelope.max(1e-9).log10() - THRESHOLD_DB;
+        let reduction = if over > 0.0 { over * (1.0 - 1.0 / RATIO) } else { 0.0 };
+        let gain = 10_f64.powf((MAKEUP_DB - reduction) / 20.0);
+        frame.iter_mut().for_each(|x| *x *= gain);
+    }
+}
+
+/// applies the [`Dsp`] settings to its input, one frame of channels at a time
+pub struct Effects<S> {
+    input: S,
+    dsp: Arc<Dsp>,
+    version: u32,
+    settings: Settings,
+    rate: u32,
+    channels: u16,
+    preamp: f64,
+    /// per channel EQ bands
+    filters: Vec<Vec<Biquad>>,
+    compressor: Compressor,
+    frame: Vec<i16>,
+    position: usize,
+}
+
+impl<S> Effects<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(input: S, dsp: Arc<Dsp>) -> Self {
+        let mut effects = Effects {
+            rate: input.sample_rate(),
+            channels: input.channels(),
+            input,
+            dsp,
+            version: 0,
+            settings: Settings::default(),
+            preamp: 1.0,
+            filters: Vec::new(),
+            compressor: Compressor::default(),
+            frame: Vec::new(),
+            position: 0,
+        };
+        effects.rebuild();
+        effects
+    }
+
+    /// pick up new settings or a new input format, resetting filter state
+    fn rebuild(&mut self) {
+        self.version = self.dsp.version.load(Ordering::Acquire);
+        self.settings = self.dsp.settings();
+        self.rate = self.input.sample_rate();
+        self.channels = self.input.channels();
+        self.preamp = self.settings.preamp();
+        self.filters = (0..self.channels)
+            .map(|_| {
+                self.settings
+                    .bands
+                    .iter()
+                    .filter_map(|x| x.filter(self.rate))
+                    .collect()
+            })
+            .collect();
+        self.compressor = Compressor::new(self.rate);
+    }
+
+    fn fill(&mut self) -> Option<()> {
+        if self.version != self.dsp.version.load(Ordering::Acquire)
+            || self.rate != self.input.sample_rate()
+            || self.channels != self.input.channels()
+        {
+            self.rebuild();
+        }
+
+        self.frame.clear();
+        self.position = 0;
+        for _ in 0..self.channels {
+            match self.input.next() {
+                Some(x) => self.frame.push(x),
+                None => break,
+            }
+        }
+        if self.frame.is_empty() {
+            return None;
+        }
+        if self.settings.is_flat() || self.frame.len() < self.channels as usize {
+            return Some(());
+        }
+
+        let mut frame = self
+            .frame
+            .iter()
+            .zip(self.filters.iter_mut())
+            .map(|(&x, filters)| {
+                let x = x as f64 / 32768.0 * self.preamp;
+                filters.iter_mut().fold(x, |x, f| f.process(x))
+            })
+            .collect::<Vec<_>>();
+        if self.settings.mono {
+            let mean = frame.iter().sum::<f64>() / frame.len() as f64;
+            frame.iter_mut().for_each(|x| *x = mean);
+        }
+        if self.settings.compressor {
+            self.compressor.process(&mut frame);
+        }
+        for (out, x) in self.frame.iter_mut().zip(frame) {
+            *out = (x * 32768.0).clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+        }
+        Some(())
+    }
+}
+
+impl<S> Iterator for Effects<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        if self.position == self.frame.len() {
+            self.fill()?;
+        }
+        let x = self.frame[self.position];
+        self.position += 1;
+        Some(x)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Source for Effects<S>
+where
+    S: Source<Item = i16>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        let buffered = self.frame.len() - self.position;
+        match self.input.current_frame_len() {
+            Some(n) => Some(n + buffered),
+            None if buffered > 0 => Some(buffered),
+            None => None,
+        }
+    }
+    #[inline]
+    fn channels(&self) -> u16 {
+        if self.position < self.frame.len() {
+            self.channels
+        } else {
+            self.input.channels()
+        }
+    }
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        if self.position < self.frame.len() {
+            self.rate
+        } else {
+            self.input.sample_rate()
+        }
+    }
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
@@ -0,0 +1,128 @@
+use rodio::{Sample, Source};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// gain shared between a [`Fade`] source and the player, stored as f32 bits
+#[derive(Debug)]
+pub struct Gain {
+    target: AtomicU32,
+    current: AtomicU32,
+    millis: AtomicU32,
+}
+
+impl Gain {
+    pub fn new(level: f32) -> Self {
+        Gain {
+            target: AtomicU32::new(level.to_bits()),
+            current: AtomicU32::new(level.to_bits()),
+            millis: AtomicU32::new(0),
+        }
+    }
+
+    /// ramp linearly from the current level to `level` over `duration`
+    pub fn fade_to(&self, level: f32, duration: Duration) {
+        self.millis
+            .store(duration.as_millis().min(u32::MAX as u128) as u32, Ordering::Relaxed);
+        self.target.store(level.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.current.load(Ordering::Relaxed))
+    }
+
+    fn target(&self) -> f32 {
+        f32::from_bits(self.target.load(Ordering::Relaxed))
+    }
+
+    /// faded out completely
+    pub fn is_silent(&self) -> bool {
+        self.target() == 0.0 && self.level() == 0.0
+    }
+}
+
+/// applies a [`Gain`] to every sample, moving towards its target a little
+/// per sample so the ramp is independent of how often the player polls
+pub struct Fade<S> {
+    input: S,
+    gain: Arc<Gain>,
+    current: f32,
+}
+
+impl<S> Fade<S> {
+    pub fn new(input: S, gain: Arc<Gain>) -> Self {
+        let current = gain.level();
+        Fade {
+            input,
+            gain,
+            current,
+        }
+    }
+}
+
+impl<S> Fade<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn step(&mut self) {
+        let target = self.gain.target();
+        if self.current == target {
+            return;
+        }
+        let millis = self.gain.millis.load(Ordering::Relaxed);
+        let samples = millis as f32 / 1000.0
+            * self.input.sample_rate() as f32
+            * self.input.channels() as f32;
+        self.current = if samples < 1.0 {
+            target
+        } else if self.current < target {
+            (self.current + 1.0 / samples).min(target)
+        } else {
+            (self.current - 1.0 / samples).max(target)
+        };
+        self.gain.current.store(self.current.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl<S> Iterator for Fade<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<S::Item> {
+        self.step();
+        self.input.next().map(|x| x.amplify(self.current))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Source for Fade<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
@@ -0,0 +1,111 @@
+use std::f64::consts::PI;
+
+/// second order IIR section, transposed direct form II
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// coefficients normalized by a0
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Biquad { b, a, z: [0.0; 2] }
+    }
+
+    /// first stage of the BS.1770 K-weighting, a high shelf modelling the head
+    pub fn k_shelf(rate: u32) -> Self {
+        let (f0, g, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / rate as f64).tan();
+        let vh = 10_f64.powf(g / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        )
+    }
+
+    /// second stage of the BS.1770 K-weighting, the RLB high pass
+    pub fn k_highpass(rate: u32) -> Self {
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / rate as f64).tan();
+        let a0 = 1.0 + k / q + k * k;
+        Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        )
+    }
+
+    /// RBJ cookbook filters
+    pub fn peaking(rate: u32, f0: f64, q: f64, gain_db: f64) -> Self {
+        let (a, cos, alpha) = Self::rbj(rate, f0, q, gain_db);
+        let a0 = 1.0 + alpha / a;
+        Biquad::new(
+            [(1.0 + alpha * a) / a0, -2.0 * cos / a0, (1.0 - alpha * a) / a0],
+            [-2.0 * cos / a0, (1.0 - alpha / a) / a0],
+        )
+    }
+
+    pub fn low_shelf(rate: u32, f0: f64, q: f64, gain_db: f64) -> Self {
+        let (a, cos, alpha) = Self::rbj(rate, f0, q, gain_db);
+        let sq = 2.0 * a.sqrt() * alpha;
+        let a0 = (a + 1.0) + (a - 1.0) * cos + sq;
+        Biquad::new(
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + sq) / a0,
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos) / a0,
+                a * ((a + 1.0) - (a - 1.0) * cos - sq) / a0,
+            ],
+            [
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos) / a0,
+                ((a + 1.0) + (a - 1.0) * cos - sq) / a0,
+            ],
+        )
+    }
+
+    pub fn high_shelf(rate: u32, f0: f64, q: f64, gain_db: f64) -> Self {
+        let (a, cos, alpha) = Self::rbj(rate, f0, q, gain_db);
+        let sq = 2.0 * a.sqrt() * alpha;
+        let a0 = (a + 1.0) - (a - 1.0) * cos + sq;
+        Biquad::new(
+            [
+                a * ((a + 1.0) + (a - 1.0) * cos + sq) / a0,
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos) / a0,
+                a * ((a + 1.0) + (a - 1.0) * cos - sq) / a0,
+            ],
+            [
+                2.0 * ((a - 1.0) - (a + 1.0) * cos) / a0,
+                ((a + 1.0) - (a - 1.0) * cos - sq) / a0,
+            ],
+        )
+    }
+
+    pub fn high_pass(rate: u32, f0: f64, q: f64) -> Self {
+        let (_, cos, alpha) = Self::rbj(rate, f0, q, 0.0);
+        let a0 = 1.0 + alpha;
+        Biquad::new(
+            [(1.0 + cos) / 2.0 / a0, -(1.0 + cos) / a0, (1.0 + cos) / 2.0 / a0],
+            [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        )
+    }
+
+    /// amplitude, cos(w0) and alpha
+    fn rbj(rate: u32, f0: f64, q: f64, gain_db: f64) -> (f64, f64, f64) {
+        let w0 = 2.0 * PI * f0 / rate as f64;
+        (10_f64.powf(gain_db / 40.0), w0.cos(), w0.sin() / (2.0 * q))
+    }
+
+    #[inline]
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
@@ -0,0 +1,337 @@
+use crate::audio::filter::Biquad;
+use rodio::{Sample, Source};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// ReplayGain 2.0 reference level
+const TARGET_LUFS: f64 = -18.0;
+const MAX_GAIN_DB: f64 = 12.0;
+/// BS.1770 gates
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+/// blocks of 400 ms advance in hops of 100 ms
+const HOPS_PER_SEC: u32 = 10;
+const HOPS_PER_BLOCK: usize = 4;
+/// blocks measured before the learned gain replaces the remembered one
+const SETTLE_BLOCKS: u32 = 100;
+/// blocks averaged into the integrated loudness, about 10 minutes
+const WINDOW_BLOCKS: u32 = 6000;
+/// gain changes are smoothed over about a second
+const SMOOTHING_SEC: f32 = 1.0;
+/// peaks are held under -1 dBFS, the limiter letting go over half a second
+const CEILING: f32 = 0.891;
+const RELEASE_SEC: f32 = 0.5;
+
+/// loudness of one station, shared between a [`Normalize`] source and the player
+#[derive(Debug)]
+pub struct Loudness {
+    enabled: AtomicBool,
+    /// gain applied when enabled, remembered or learned
+    gain_db: AtomicU32,
+    /// integrated loudness, NaN until measured
+    lufs: AtomicU32,
+    blocks: AtomicU32,
+}
+
+impl Loudness {
+    pub fn new(enabled: bool, gain_db: f32) -> Self {
+        Loudness {
+            enabled: AtomicBool::new(enabled),
+            gain_db: AtomicU32::new(gain_db.to_bits()),
+            lufs: AtomicU32::new(f32::NAN.to_bits()),
+            blocks: AtomicU32::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn gain_db(&self) -> f32 {
+        f32::from_bits(self.gain_db.load(Ordering::Relaxed))
+    }
+
+    /// start from a remembered gain, until the measurement settles
+    pub fn set_gain_db(&self, gain_db: f32) {
+        if !self.settled() {
+            self.gain_db.store(gain_db.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn lufs(&self) -> Option<f32> {
+        Some(f32::from_bits(self.lufs.load(Ordering::Relaxed))).filter(|x| !x.is_nan())
+    }
+
+    /// enough audio was measured to trust the learned gain
+    pub fn settled(&self) -> bool {
+        self.blocks.load(Ordering::Relaxed) >= SETTLE_BLOCKS
+    }
+
+    fn measured(&self, lufs: f64, blocks: u32) {
+        self.lufs.store((lufs as f32).to_bits(), Ordering::Relaxed);
+        self.blocks.store(blocks, Ordering::Relaxed);
+        if blocks >= SETTLE_BLOCKS {
+            let gain_db = (TARGET_LUFS - lufs).clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
+            self.gain_db.store((gain_db as f32).to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// BS.1770 K-weighted, gated loudness meter
+struct Meter {
+    rate: u32,
+    channels: u16,
+    filters: Vec<[Biquad; 2]>,
+    hop_len: usize,
+    count: usize,
+    sum: f64,
+    hops: [f64; HOPS_PER_BLOCK],
+    filled: usize,
+    /// mean energy of the gated blocks
+    energy: f64,
+    blocks: u32,
+}
+
+impl Meter {
+    fn new(rate: u32, channels: u16) -> Self {
+        Meter {
+            rate,
+            channels,
+            filters: (0..channels)
+                .map(|_| [Biquad::k_shelf(rate), Biquad::k_highpass(rate)])
+                .collect(),
+            hop_len: (rate / HOPS_PER_SEC) as usize * channels as usize,
+            count: 0,
+            sum: 0.0,
+            hops: [0.0; HOPS_PER_BLOCK],
+            filled: 0,
+            energy: 0.0,
+            blocks: 0,
+        }
+    }
+
+    fn loudness(energy: f64) -> f64 {
+        -0.691 + 10.0 * energy.log10()
+    }
+
+    /// integrated loudness
+    fn integrated(&self) -> Option<f64> {
+        (self.blocks > 0).then(|| Self::loudness(self.energy))
+    }
+
+    /// feed one sample, true when a hop completed
+    fn push(&mut self, x: f64, channel: usize) -> bool {
+        let [shelf, highpass] = &mut self.filters[channel];
+        let y = highpass.process(shelf.process(x));
+        self.sum += y * y;
+        self.count += 1;
+        if self.count < self.hop_len {
+            return false;
+        }
+
+        self.hops.rotate_left(1);
+        self.hops[HOPS_PER_BLOCK - 1] = self.sum / (self.hop_len / self.channels as usize) as f64;
+        self.sum = 0.0;
+        self.count = 0;
+        self.filled = (self.filled + 1).min(HOPS_PER_BLOCK);
+        if self.filled == HOPS_PER_BLOCK {
+            let block = self.hops.iter().sum::<f64>() / HOPS_PER_BLOCK as f64;
+            let lufs = Self::loudness(block);
+            let gate = self.integrated().map_or(ABSOLUTE_GATE, |x| x + RELATIVE_GATE);
+            if lufs > ABSOLUTE_GATE && lufs > gate {
+                self.blocks += 1;
+                self.energy += (block - self.energy) / self.blocks.min(WINDOW_BLOCKS) as f64;
+            }
+        }
+        true
+    }
+}
+
+/// measures the loudness of its input and, when enabled, applies the gain
+/// that brings it to the reference level; a boost is held back on peaks
+/// rather than clipped
+pub struct Normalize<S> {
+    input: S,
+    loudness: Arc<Loudness>,
+    meter: Meter,
+    channel: usize,
+    gain: f32,
+    target: f32,
+    /// input peak, jumping up at once and decaying with the release
+    peak: f32,
+}
+
+impl<S> Normalize<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(input: S, loudness: Arc<Loudness>) -> Self {
+        let meter = Meter::new(input.sample_rate(), input.channels());
+        let mut normalize = Normalize {
+            input,
+            loudness,
+            meter,
+            channel: 0,
+            gain: 1.0,
+            target: 1.0,
+            peak: 0.0,
+        };
+        normalize.update();
+        normalize.gain = normalize.target;
+        normalize
+    }
+
+    fn update(&mut self) {
+        if let Some(lufs) = self.meter.integrated() {
+            self.loudness.measured(lufs, self.meter.blocks);
+        }
+        self.target = if self.loudness.is_enabled() {
+            10_f32.powf(self.loudness.gain_db() / 20.0)
+        } else {
+            1.0
+        };
+    }
+}
+
+impl<S> Iterator for Normalize<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        if self.channel == 0
+            && (self.input.sample_rate() != self.meter.rate
+                || self.input.channels() != self.meter.channels)
+        {
+            // keep the learned loudness across format changes
+            let (energy, blocks) = (self.meter.energy, self.meter.blocks);
+            self.meter = Meter::new(self.input.sample_rate(), self.input.channels());
+            (self.meter.energy, self.meter.blocks) = (energy, blocks);
+        }
+        let x = self.input.next()?;
+
+        if self.meter.push(x as f64 / 32768.0, self.channel) {
+            self.update();
+        }
+        self.channel = (self.channel + 1) % self.meter.channels as usize;
+
+        let rate = self.meter.rate as f32 * self.meter.channels as f32;
+        self.gain += (self.target - self.gain) / (SMOOTHING_SEC * rate);
+        self.peak = (x as f32 / 32768.0)
+            .abs()
+            .max(self.peak * (1.0 - 1.0 / (RELEASE_SEC * rate)));
+        // a cut never clips, a boost only as far as the peak allows
+        let gain = self.gain.min((CEILING / self.peak).max(1.0));
+        Some(x.amplify(gain))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Source for Normalize<S>
+where
+    S: Source<Item = i16>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+    use std::f64::consts::PI;
+
+    const RATE: u32 = 48000;
+
+    /// seconds of a 997 Hz sine at `dbfs`, the same on every channel
+    fn sine(dbfs: f64, channels: u16, secs: u32) -> Vec<f64> {
+        let amplitude = 10_f64.powf(dbfs / 20.0);
+        (0..RATE * secs)
+            .flat_map(|i| {
+                let x = amplitude * (2.0 * PI * 997.0 * i as f64 / RATE as f64).sin();
+                std::iter::repeat_n(x, channels as usize)
+            })
+            .collect()
+    }
+
+    fn measure(samples: &[f64], channels: u16) -> f64 {
+        let mut meter = Meter::new(RATE, channels);
+        for (i, x) in samples.iter().enumerate() {
+            meter.push(*x, i % channels as usize);
+        }
+        meter.integrated().unwrap()
+    }
+
+    #[test]
+    fn sine_level() {
+        // BS.1770: a full scale 1 kHz sine on one channel is -3.01 LKFS
+        let lufs = measure(&sine(-20.0, 1, 5), 1);
+        assert!((lufs - -23.01).abs() < 0.1, "{}", lufs);
+        // the channels add up
+        let lufs = measure(&sine(-20.0, 2, 5), 2);
+        assert!((lufs - -20.0).abs() < 0.1, "{}", lufs);
+    }
+
+    #[test]
+    fn gates() {
+        // silence is below the absolute gate, it does not pull the level down;
+        // only the few blocks straddling the edge count a little quieter
+        let mut samples = sine(-30.0, 1, 5);
+        samples.extend(vec![0.0; RATE as usize * 5]);
+        let lufs = measure(&samples, 1);
+        assert!((lufs - -33.01).abs() < 0.25, "{}", lufs);
+        // much quieter passages fall under the relative gate
+        samples.extend(sine(-60.0, 1, 5));
+        let lufs = measure(&samples, 1);
+        assert!((lufs - -33.01).abs() < 0.25, "{}", lufs);
+    }
+
+    #[test]
+    fn peaks_not_clipped() {
+        // a quiet station boosted by the full 12 dB, then a full scale hit
+        let mut samples = sine(-30.0, 1, 2);
+        samples.extend(sine(0.0, 1, 1));
+        samples.extend(sine(-30.0, 1, 2));
+        let input = samples.iter().map(|x| (x * 32767.0) as i16).collect::<Vec<_>>();
+        let loudness = Arc::new(Loudness::new(true, MAX_GAIN_DB as f32));
+        let out = Normalize::new(SamplesBuffer::new(1, RATE, input.clone()), loudness).collect::<Vec<_>>();
+
+        // nothing is boosted past the ceiling, what was louder is left alone
+        let ceiling = (CEILING * 32768.0) as u16 + 1;
+        assert!(out.iter().zip(&input).all(|(y, x)| y.unsigned_abs() <= ceiling.max(x.unsigned_abs())));
+        let boost = |from: usize| {
+            let peak = |x: &[i16]| x[from..from + 1000].iter().map(|x| x.unsigned_abs()).max().unwrap() as f32;
+            peak(&out) / peak(&input)
+        };
+        // the quiet part before is boosted by about 12 dB
+        let full = 10_f32.powf(MAX_GAIN_DB as f32 / 20.0);
+        assert!((boost(RATE as usize) - full).abs() < 0.1, "{}", boost(RATE as usize));
+        // and the boost comes back after the release
+        assert!((boost(out.len() - 1000) - full).abs() < 0.1, "{}", boost(out.len() - 1000));
+    }
+}
@@ -1,4 +1,8 @@
 pub mod assets;
+pub mod dsp;
+pub mod fade;
+pub mod filter;
+pub mod loudness;
 pub mod player;
 pub mod sink;
 pub mod stream;
@@ -1,46 +1,366 @@
-use log::info;
+use log::{error, info};
 use crate::api::worker::rand;
 use crate::audio::assets::ASSETS;
+use crate::audio::dsp::{Dsp, Effects, Settings};
+use crate::audio::fade::{Fade, Gain};
+use crate::audio::loudness::{Loudness, Normalize};
 use crate::audio::sink::MusicStruct;
 use crate::audio::stream::StreamPipe;
-use rodio::Sink;
+use crate::terminal;
+use crate::terminal::args::Options;
+use crate::util::store;
+use anyhow::Result;
+use rodio::{OutputStreamHandle, Sink};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub struct Player {
+/// default crossfade between stations
+const CROSSFADE: Duration = Duration::from_millis(2000);
+/// learned station gains are written out at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+const LOUDNESS_FILE: &str = "loudness.json";
+
+/// loudness normalization state remembered between sessions
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LoudnessStore {
+    enabled: bool,
+    /// learned gain in dB by station id
+    gains: HashMap<String, f32>,
+}
+
+/// one decoding chain: pipe -> decoder -> normalize -> effects -> fade -> sink
+struct Channel {
     sink: Sink,
     pipe: StreamPipe,
+    stats: Arc<redlux::Stats>,
+    gain: Arc<Gain>,
+    loudness: Arc<Loudness>,
+    station: Option<String>,
+}
+
+impl Channel {
+    fn new(
+        handle: &OutputStreamHandle,
+        level: f32,
+        volume: f32,
+        normalize: bool,
+        dsp: &Arc<Dsp>,
+    ) -> Self {
+        let stream = StreamPipe::default();
+        let pipe = stream.clone();
+        let dec = redlux::Decoder::new_aac(stream);
+        let stats = dec.stats();
+        let gain = Arc::new(Gain::new(level));
+        let loudness = Arc::new(Loudness::new(normalize, 0.0));
+        let sink = Sink::try_new(handle).unwrap();
+        sink.set_volume(volume);
+        sink.append(Fade::new(
+            Effects::new(Normalize::new(dec, Arc::clone(&loudness)), Arc::clone(dsp)),
+            Arc::clone(&gain),
+        ));
+        Channel {
+            sink,
+            pipe,
+            stats,
+            gain,
+            loudness,
+            station: None,
+        }
+    }
+}
+
+pub struct Player {
+    handle: OutputStreamHandle,
+    /// the channel being heard
+    current: Channel,
+    /// the next station, silent until buffered
+    pending: Option<Channel>,
+    /// previous stations fading out
+    fading: Vec<Channel>,
+    crossfade: Duration,
+    volume: f32,
+    store: LoudnessStore,
+    saved: Instant,
+    dsp: Arc<Dsp>,
+    /// fading out or paused by `stop`
+    stopped: bool,
+    /// the buffer was cleared on purpose and nothing was added since
+    cleared: bool,
 }
 
 impl Default for Player {
     fn default() -> Self {
         let stream_handle = MusicStruct::new();
-        let stream = StreamPipe::default();
-        let mut pipe = stream.clone();
-        let dec = redlux::Decoder::new_aac(stream);
-        let sink = Sink::try_new(&stream_handle.stream_handle.unwrap()).unwrap();
+        let handle = stream_handle.stream_handle.unwrap();
+        let store: LoudnessStore = store::load(LOUDNESS_FILE);
+        let settings = match Options::init().eq.map(|x| x.parse::<Settings>()) {
+            Some(Ok(settings)) => settings,
+            Some(Err(e)) => {
+                terminal::print_error(e);
+                Settings::default()
+            },
+            None => Settings::default(),
+        };
+        let dsp = Arc::new(Dsp::new(settings));
+        let mut current = Channel::new(&handle, 1.0, 1.0, store.enabled, &dsp);
 
-        pipe.add(&ASSETS.get(rand()));
+        current.pipe.add(&ASSETS.get(rand()));
 
-        sink.append(dec);
-        Player { sink, pipe }
+        Player {
+            handle,
+            current,
+            pending: None,
+            fading: Vec::new(),
+            crossfade: Options::init()
+                .crossfade
+                .map(Duration::from_millis)
+                .unwrap_or(CROSSFADE),
+            volume: 1.0,
+            store,
+            saved: Instant::now(),
+            dsp,
+            stopped: false,
+            cleared: false,
+        }
     }
 }
 
 impl Player {
+    /// the channel new data goes to
+    fn input(&mut self) -> &mut Channel {
+        self.pending.as_mut().unwrap_or(&mut self.current)
+    }
+
     pub fn add(&mut self, buf: &[u8]) {
-        self.pipe.add(buf);
+        if self.stopped {
+            return;
+        }
+        self.input().pipe.add(buf);
+        self.cleared = false;
     }
 
     pub fn volume(&mut self, level: char) {
-        self.sink
-            .set_volume((level.to_digit(10).unwrap() as f32 / 9_f32).powf(2.0));
+        self.volume = (level.to_digit(10).unwrap() as f32 / 9_f32).powf(2.0);
+        for channel in self.channels() {
+            channel.sink.set_volume(self.volume);
+        }
+    }
+
+    fn channels(&self) -> impl Iterator<Item = &Channel> {
+        std::iter::once(&self.current)
+            .chain(self.pending.as_ref())
+            .chain(self.fading.iter())
+    }
+
+    /// switch stations: the current channel keeps playing while the next one
+    /// buffers, then `tick` crossfades between them
+    pub fn transition(&mut self) {
+        info!("transition\r");
+        if let Some(pending) = self.pending.take() {
+            // switched again before the previous one was heard
+            pending.sink.stop();
+        }
+        // a new station resumes playback, the paused channel is dropped by `tick`
+        self.stopped = false;
+        self.pending = Some(Channel::new(
+            &self.handle,
+            0.0,
+            self.volume,
+            self.store.enabled,
+            &self.dsp,
+        ));
+    }
+
+    /// the station of the channel new data goes to, starting from the gain
+    /// learned for it last time
+    pub fn set_station(&mut self, id: &str) {
+        let gain_db = self.store.gains.get(id).copied().unwrap_or_default();
+        let input = self.input();
+        input.loudness.set_gain_db(gain_db);
+        input.station = Some(id.to_string());
+        self.save_loudness();
+    }
+
+    /// toggle loudness normalization, returns the new state
+    pub fn toggle_loudness(&mut self) -> bool {
+        self.store.enabled = !self.store.enabled;
+        for channel in self.channels() {
+            channel.loudness.set_enabled(self.store.enabled);
+        }
+        self.save_loudness();
+        self.store.enabled
+    }
+
+    /// fade out over `fade`, then pause until `resume` or the next `transition`
+    pub fn stop(&mut self, fade: Duration) {
+        info!("stop {:?}\r", fade);
+        if let Some(pending) = self.pending.take() {
+            // keep the new station, silent, to resume with
+            let prev = std::mem::replace(&mut self.current, pending);
+            prev.gain.fade_to(0.0, fade);
+            self.fading.push(prev);
+        }
+        self.current.gain.fade_to(0.0, fade);
+        self.stopped = true;
+    }
+
+    pub fn resume(&mut self) {
+        info!("resume\r");
+        self.current.pipe.clear();
+        self.current.sink.play();
+        self.current.gain.fade_to(1.0, self.crossfade);
+        self.stopped = false;
+        self.cleared = true;
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// hold playback where it is, keeping the buffer, for catch-up
+    pub fn pause(&mut self, pause: bool) {
+        info!("pause {}\r", pause);
+        match pause {
+            true => self.current.sink.pause(),
+            false => self.current.sink.play(),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.current.sink.is_paused()
+    }
+
+    /// switch to the next EQ preset, returns its name
+    pub fn next_preset(&mut self) -> String {
+        let settings = self.dsp.settings().next();
+        let name = settings.to_string();
+        self.dsp.set(settings);
+        name
+    }
+
+    /// switch to an EQ preset by name, or ten band gains
+    pub fn set_eq(&mut self, preset: &str) -> Result<String> {
+        let settings = preset.parse::<Settings>()?;
+        let name = settings.to_string();
+        self.dsp.set(settings);
+        Ok(name)
+    }
+
+    /// measured loudness and applied gain of the current station
+    pub fn loudness(&self) -> (Option<f32>, f32) {
+        let loudness = &self.current.loudness;
+        (loudness.lufs(), loudness.gain_db())
+    }
+
+    fn save_loudness(&mut self) {
+        let learned = self
+            .channels()
+            .filter(|x| x.loudness.settled())
+            .filter_map(|x| Some((x.station.clone()?, x.loudness.gain_db())))
+            .collect::<Vec<_>>();
+        self.store.gains.extend(learned);
+        if let Err(e) = store::save(LOUDNESS_FILE, &self.store) {
+            error!("save loudness: {:?}\r", e);
+        }
+        self.saved = Instant::now();
+    }
+
+    /// advance transitions, called periodically
+    pub fn tick(&mut self) {
+        if self.saved.elapsed() > SAVE_INTERVAL {
+            self.save_loudness();
+        }
+        let ready = self
+            .pending
+            .as_ref()
+            .is_some_and(|x| !x.pipe.below_low());
+        if ready {
+            let next = self.pending.take().unwrap();
+            let prev = std::mem::replace(&mut self.current, next);
+            info!("crossfade {:?}\r", self.crossfade);
+            self.current.gain.fade_to(1.0, self.crossfade);
+            prev.gain.fade_to(0.0, self.crossfade);
+            self.fading.push(prev);
+        }
+        if self.stopped && self.current.gain.is_silent() && !self.current.sink.is_paused() {
+            self.current.sink.pause();
+            self.current.pipe.clear();
+        }
+        self.fading.retain(|x| {
+            if x.gain.is_silent() || x.sink.empty() {
+                x.sink.stop();
+                return false;
+            }
+            true
+        });
     }
 
     pub fn buffer_length(&self) -> usize {
-        self.pipe.buffer.lock().unwrap().len()
+        self.pending.as_ref().unwrap_or(&self.current).pipe.unread()
+    }
+
+    /// the buffer is under its low watermark
+    pub fn buffer_low(&self) -> bool {
+        self.pending.as_ref().unwrap_or(&self.current).pipe.below_low()
+    }
+
+    /// the buffer is over its high watermark, adding more risks overflow
+    pub fn buffer_full(&self) -> bool {
+        self.pending.as_ref().unwrap_or(&self.current).pipe.above_high()
+    }
+
+    /// bytes lost to overflow so far
+    pub fn buffer_dropped(&self) -> usize {
+        self.current.pipe.dropped()
+    }
+
+    /// decoder counters: underruns, corrupted frames, resyncs
+    pub fn stats(&self) -> &redlux::Stats {
+        &self.current.stats
+    }
+
+    /// the buffer ran dry and the decoder is playing silence, not counting
+    /// the gap after `buffer_clear`, while switching stations or stopped
+    pub fn starving(&self) -> bool {
+        self.current.stats.is_starving()
+            && !self.cleared
+            && !self.stopped
+            && self.pending.is_none()
     }
 
     pub fn buffer_clear(&mut self) {
         info!("buffer clear\r");
-        self.pipe.clear();
+        self.input().pipe.clear();
+        self.cleared = true;
+    }
+}
+
+/// play a local ADTS or MP4/M4A recording to the end
+/// plays a local file to the end, waking up now and then rather than blocking
+/// a runtime thread
+pub async fn play_file(path: &Path) -> Result<()> {
+    let stream_handle = MusicStruct::new();
+    let sink = Sink::try_new(&stream_handle.stream_handle.unwrap())?;
+
+    let mut file = File::open(path)?;
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic)?;
+    file.rewind()?;
+
+    if &magic[4..] == b"ftyp" {
+        let size = file.metadata()?.len();
+        sink.append(redlux::Decoder::new_mpeg4(BufReader::new(file), size)?);
+    } else {
+        sink.append(redlux::Decoder::new_aac(BufReader::new(file)));
+    }
+    info!("play {:?}\r", path);
+    while !sink.empty() {
+        tokio::time::sleep(Duration::from_millis(100)).await;
     }
+    Ok(())
 }
@@ -1,36 +1,271 @@
 use std::io;
 use std::io::Read;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 use std::sync::Arc;
 
-#[derive(Clone, Default)]
+/// 1 MiB, several minutes of a typical AAC stream
+pub const DEFAULT_CAPACITY: usize = 1 << 20;
+
+/// bounded single-producer single-consumer ring buffer
+///
+/// Read and write positions only ever grow. When full, the producer discards
+/// the oldest unread bytes to keep the stream close to live, moving the read
+/// position itself (as does clear); the consumer publishes its read with a
+/// compare-exchange and retries if the position moved under it, so bytes it
+/// copied while being overwritten are never returned.
+struct Ring {
+    buf: Box<[AtomicU8]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    dropped: AtomicUsize,
+    low: usize,
+    high: usize,
+}
+
+#[derive(Clone)]
 pub struct StreamPipe {
-    pub buffer: Arc<std::sync::Mutex<Vec<u8>>>,
-    position: usize,
+    ring: Arc<Ring>,
+}
+
+impl Default for StreamPipe {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
 }
 
 impl StreamPipe {
-    pub fn add(&mut self, data: &[u8]) {
-        self.buffer.lock().unwrap().extend(data);
+    /// low and high watermarks default to 1/64 and 3/4 of the capacity
+    pub fn new(capacity: usize) -> Self {
+        Self::with_watermarks(capacity, capacity / 64, capacity / 4 * 3)
+    }
+
+    pub fn with_watermarks(capacity: usize, low: usize, high: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            ring: Arc::new(Ring {
+                buf: (0..capacity).map(|_| AtomicU8::new(0)).collect(),
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                dropped: AtomicUsize::new(0),
+                low: low.min(capacity),
+                high: high.min(capacity),
+            }),
+        }
+    }
+
+    /// producer side, returns the number of bytes stored
+    pub fn add(&mut self, data: &[u8]) -> usize {
+        let ring = &self.ring;
+        let cap = ring.buf.len();
+        ring.dropped
+            .fetch_add(data.len().saturating_sub(cap), Ordering::Relaxed);
+        let data = &data[data.len().saturating_sub(cap)..];
+
+        let head = ring.head.load(Ordering::Relaxed);
+        loop {
+            let tail = ring.tail.load(Ordering::Acquire);
+            let free = cap - head.wrapping_sub(tail);
+            if data.len() <= free {
+                break;
+            }
+            let over = data.len() - free;
+            if ring
+                .tail
+                .compare_exchange(
+                    tail,
+                    tail.wrapping_add(over),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+            {
+                ring.dropped.fetch_add(over, Ordering::Relaxed);
+                break;
+            }
+        }
+
+        for (i, b) in data.iter().enumerate() {
+            ring.buf[head.wrapping_add(i) % cap].store(*b, Ordering::Relaxed);
+        }
+        ring.head.store(head.wrapping_add(data.len()), Ordering::Release);
+        data.len()
     }
+
     pub fn clear(&mut self) {
-        self.buffer.lock().unwrap().clear();
+        let ring = &self.ring;
+        loop {
+            let tail = ring.tail.load(Ordering::Acquire);
+            let head = ring.head.load(Ordering::Acquire);
+            if ring
+                .tail
+                .compare_exchange(tail, head, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                break;
+            }
+        }
+    }
+
+    /// bytes not read yet
+    pub fn unread(&self) -> usize {
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        self.ring.head.load(Ordering::Acquire).wrapping_sub(tail)
+    }
+
+    /// bytes discarded by the overflow policy so far
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped.load(Ordering::Relaxed)
+    }
+
+    /// time to fetch more
+    pub fn below_low(&self) -> bool {
+        self.unread() < self.ring.low
+    }
+
+    /// time to hold back
+    pub fn above_high(&self) -> bool {
+        self.unread() > self.ring.high
     }
 }
+
 impl Read for StreamPipe {
+    /// consumer side
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let mut vec = self
-            .buffer
-            .lock()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to lock mutex"))?;
+        let ring = &self.ring;
+        let cap = ring.buf.len();
+        loop {
+            let tail = ring.tail.load(Ordering::Acquire);
+            let head = ring.head.load(Ordering::Acquire);
+            let to_read = head.wrapping_sub(tail).min(buf.len());
+            if to_read == 0 && !buf.is_empty() {
+                // live stream, more data is on the way
+                return Err(io::Error::from(io::ErrorKind::WouldBlock));
+            }
 
-        let remaining = vec.len() - self.position;
-        let to_read = remaining.min(buf.len());
+            // Copy data into buffer
+            for (i, b) in buf[..to_read].iter_mut().enumerate() {
+                *b = ring.buf[tail.wrapping_add(i) % cap].load(Ordering::Relaxed);
+            }
 
-        // Copy data into buffer
-        buf[..to_read].copy_from_slice(&vec[self.position..self.position + to_read]);
+            // publish, unless the producer moved the tail meanwhile
+            if ring
+                .tail
+                .compare_exchange(
+                    tail,
+                    tail.wrapping_add(to_read),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+            {
+                return Ok(to_read);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn read_all(pipe: &mut StreamPipe) -> Vec<u8> {
+        let mut buf = [0; 64];
+        match pipe.read(&mut buf) {
+            Ok(n) => buf[..n].to_vec(),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+                vec![]
+            },
+        }
+    }
+
+    #[test]
+    fn wraparound() {
+        let mut pipe = StreamPipe::new(8);
+        let mut reader = pipe.clone();
+        assert_eq!(pipe.add(b"abcdef"), 6);
+        let mut buf = [0; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"abcd");
+        // the write wraps past the end of the buffer
+        assert_eq!(pipe.add(b"ghijk"), 5);
+        assert_eq!(pipe.unread(), 7);
+        assert_eq!(read_all(&mut reader), b"efghijk");
+        assert_eq!(pipe.dropped(), 0);
+        assert!(read_all(&mut reader).is_empty());
+    }
+
+    #[test]
+    fn overflow() {
+        let mut pipe = StreamPipe::new(8);
+        let mut reader = pipe.clone();
+        pipe.add(b"abcdef");
+        // the oldest unread bytes make room
+        assert_eq!(pipe.add(b"ghij"), 4);
+        assert_eq!(pipe.dropped(), 2);
+        assert_eq!(read_all(&mut reader), b"cdefghij");
+        // more than fits at once keeps the newest
+        assert_eq!(pipe.add(b"0123456789"), 8);
+        assert_eq!(pipe.dropped(), 4);
+        assert_eq!(read_all(&mut reader), b"23456789");
+    }
+
+    #[test]
+    fn overwritten_while_reading() {
+        // the producer keeps overrunning a small ring; every read must be a
+        // run of consecutive bytes, never a copy torn by an overwrite
+        let mut pipe = StreamPipe::new(64);
+        let mut reader = pipe.clone();
+        let producer = thread::spawn(move || {
+            let data = (0..=255).collect::<Vec<u8>>();
+            for _ in 0..20000 {
+                for chunk in data.chunks(48) {
+                    pipe.add(chunk);
+                }
+            }
+            pipe
+        });
+        let mut reads = 0;
+        while !producer.is_finished() {
+            let got = read_all(&mut reader);
+            assert!(got.windows(2).all(|x| x[1] == x[0].wrapping_add(1)), "{:?}", got);
+            reads += !got.is_empty() as usize;
+        }
+        let pipe = producer.join().unwrap();
+        assert!(reads > 0);
+        assert!(pipe.dropped() > 0);
+    }
+
+    #[test]
+    fn clear() {
+        let mut pipe = StreamPipe::new(8);
+        let mut reader = pipe.clone();
+        pipe.add(b"abcdef");
+        pipe.clear();
+        assert_eq!(pipe.unread(), 0);
+        assert!(read_all(&mut reader).is_empty());
+        // clearing is not dropping, and the ring is usable after
+        assert_eq!(pipe.dropped(), 0);
+        pipe.add(b"gh");
+        assert_eq!(read_all(&mut reader), b"gh");
+    }
 
-        // drain
-        vec.drain(..to_read);
-        Ok(to_read)
+    #[test]
+    fn watermarks() {
+        let mut pipe = StreamPipe::with_watermarks(16, 4, 12);
+        assert!(pipe.below_low() && !pipe.above_high());
+        pipe.add(&[0; 4]);
+        assert!(!pipe.below_low() && !pipe.above_high());
+        pipe.add(&[0; 8]);
+        assert!(!pipe.above_high());
+        pipe.add(&[0; 1]);
+        assert!(pipe.above_high());
+        // the defaults are 1/64 and 3/4 of the capacity
+        let mut pipe = StreamPipe::new(256);
+        pipe.add(&[0; 3]);
+        assert!(pipe.below_low());
+        pipe.add(&[0; 190]);
+        assert!(pipe.above_high());
     }
 }
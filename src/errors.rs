@@ -17,6 +17,46 @@ pub enum RadicoError {
     AuthError,
     #[error("Forbidden")]
     Forbidden,
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("HTTP status {}", .0)]
+    Status(u16),
+    #[error("Gave up after {} attempts", .0)]
+    RetryExhausted(u32),
+    #[error("Unknown command {}", .0)]
+    CommandError(String),
+    #[error("Unknown EQ preset {}", .0)]
+    EqPreset(String),
+    #[error("Invalid alarm {}, expected HH:MM[,days][,station]", .0)]
+    AlarmError(String),
+    #[error("Invalid sleep timer {}, expected off, end or a duration like 45m", .0)]
+    TimerError(String),
+    #[error("Unknown station {}", .0)]
+    UnknownStation(String),
+    #[error("No stations in area {}", .0)]
+    AreaError(String),
+    #[error("Invalid date {}, expected YYYYMMDD", .0)]
+    DateError(String),
+    #[error("--station is required with --at")]
+    MissingStation,
+    #[error("--at is required with --station")]
+    MissingTime,
+    #[error("No past program at {}", .0)]
+    NoProgram(String),
+    #[error("Invalid time {}, expected YYYY-MM-DDThh:mm", .0)]
+    TimeError(String),
+    #[error("Invalid segment: {}", .0)]
+    SegmentError(String),
+    #[error("{} is past the end of the program at {}", .0, .1)]
+    PastEnd(String, String),
+    #[error("Only {} of {} seconds are available", .0, .1)]
+    Truncated(i64, i64),
+    #[error("{} segments failed, run again to resume", .0)]
+    Incomplete(usize),
+    #[error("No program {}, expected <station>@YYYYMMDDhhmmss from the search", .0)]
+    NoMatch(String),
+    #[error("Nothing to search for in {:?}", .0)]
+    EmptyQuery(String),
     #[error("Local time is negative {} ms", .0)]
     NegativeTime(i64),
     #[error("Quit")]
@@ -1,4 +1,10 @@
+use crate::api::{download, find, listing, record, timefree};
 use crate::api::worker::Queue;
+use crate::audio::player;
+use crate::errors::RadicoError;
+use crate::terminal::args::{Cmd, Options};
+use crate::util::{hooks, ipc};
+use log::error;
 #[allow(unused_imports)]
 use crate::logger::Logger;
 
@@ -11,9 +17,87 @@ mod logger;
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 2)]
 async fn main() {
+    hooks::init(Options::init().hook, Options::init().notify);
+    if let Some(cmd) = Options::init().cmd {
+        let Some(req) = cmd.request() else {
+            let res = match cmd {
+                Cmd::Play { station, at, url } => match at {
+                    Some(at) => timefree::play(station, at, url).await,
+                    None => Err(anyhow::Error::from(RadicoError::MissingTime)),
+                },
+                Cmd::Download {
+                    station,
+                    from,
+                    to,
+                    output,
+                    jobs,
+                    logo,
+                    url,
+                } => download::run(station, from, to, output, jobs, logo, url).await,
+                Cmd::Search {
+                    days,
+                    station,
+                    area,
+                    record,
+                    format,
+                    query,
+                    url,
+                } => find::run(query, days, station, area, record, format, url).await,
+                Cmd::Record {
+                    wait,
+                    list,
+                    format,
+                    output,
+                    url,
+                } => record::run(wait, list, format, output, url).await,
+                // listing commands, before anything opens the audio device
+                cmd => listing::run(cmd).await,
+            };
+            // these leave the terminal as it is, their output may be piped
+            if let Err(e) = res {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        };
+        // forward to the running instance
+        match ipc::request(&req).await {
+            Ok(res) => {
+                let json = serde_json::from_str::<serde_json::Value>(&res).unwrap_or_default();
+                if let Some(e) = json.get("error") {
+                    eprintln!("{}", e.as_str().unwrap_or_default());
+                    std::process::exit(1);
+                }
+                println!("{}", res);
+            },
+            Err(e) => {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+    // before the session is set up, which takes a while; requests meanwhile
+    // are told to wait
+    match ipc::bind().await {
+        Ok(Some(listener)) => ipc::serve(listener),
+        Ok(None) => {
+            eprintln!("radico is already running, control it with radico next, prev, tune, volume, stop");
+            std::process::exit(1);
+        },
+        Err(e) => error!("ipc: {:?}\r", e),
+    }
+
     let _exit = terminal::Quit;
     terminal::init();
     // let _logger = Logger::build(2);
+    if let Some(file) = Options::init().file {
+        if let Err(e) = player::play_file(&file).await {
+            terminal::print_error(e);
+        }
+        return;
+    }
+
     let mut m = Queue::default();
 
     if let Err(e) = m.worker().await {
@@ -1,7 +1,10 @@
-use bpaf::{Bpaf, Parser, short};
+use bpaf::{construct, long, short, Bpaf, Parser};
 use std::fmt::Debug;
 use std::io;
 use std::path::PathBuf;
+use std::sync::LazyLock;
+use crate::util::alarm::Alarm;
+use crate::util::timer::Timer;
 use crossterm::{cursor, execute};
 
 const ABOUT: &str = "
@@ -9,15 +12,47 @@ A command line music player for Internet Radio.
 
 [Key]                [Description]
  0-9                  adjust volume
+ e                    cycle EQ presets
+ g                    play an earlier program of today
  i                    station info
+ m                    pick a region and station
+ l                    toggle loudness normalization
  n                    next station
  p                    previous station
+ s                    sleep timer 15/30/60 min, end of program, off
+ x                    stop alarm
+ z                    snooze alarm
  Q                    quit
  Ctrl+C               exit
 ";
 
 const USAGE: &str = "
-Usage: radico [-s] [--cert=<cert>] [--proxy=<socks>] [url]
+Usage: radico status | next | prev | tune <station> | volume <0-9> | stop | play | eq [<preset>]
+       radico play --station=<station> --at=<time> <url>
+       radico download --station=<station> --from=<time> [--to=<time>] [-o <file>] [--jobs=<n>] [--logo] <url>
+       radico stations [--area=<area>] [--json | --csv] <url>
+       radico areas [--json | --csv] <url>
+       radico guide [--date=<date>] [--json | --csv] <station> <url>
+       radico search [--days=<n>] [--station=<station>] [--area=<area>] [--record=<key>] [--json | --csv] <query> <url>
+       radico record [--wait] [--list [--json | --csv]] [-o <dir>] <url>
+       radico [-s] [--cert=<cert>] [--proxy=<socks>] [--latency=<sec>] [--retries=<n>] [--file=<file>] [--station=<station>] [--area=<area>] [--crossfade=<ms>] [--eq=<preset>] [--sleep=<duration>] [--alarm=<alarm>]... [--hook=<cmd>]... [--notify] [--status-file=<path>] [url]
+
+Available commands:
+    status               print the state of the running instance as JSON
+    next, prev           switch the running instance to the next/previous station
+    tune <station>       switch the running instance to a station id or name
+    volume <0-9>         set the volume of the running instance
+    stop, play           stop or resume the running instance
+    play --at=<time>     play the program on air at a past time, in Japan time, ex: 2026-10-17T21:00
+    eq [<preset>]        set or cycle the EQ preset of the running instance
+    download             save a past program, or --from to --to, to an AAC file
+    stations             list stations, all or those of --area=JP13
+    areas                list areas
+    guide <station>      print the program guide of a station for --date=YYYYMMDD
+    search <query>       find the programs of the coming week by title or performer
+                         and schedule one with --record=<key>
+    record               save the scheduled programs that have aired, --wait to
+                         keep running for those still to come
 
 Available positional items:
     url                  url
@@ -26,6 +61,18 @@ Available options:
     -s, --show-dev-list  show device list
         --cert=<cert>    certificate
         --proxy=<socks>  ex: [https|socks5]://<ip>:<port>
+        --latency=<sec>  target latency behind live
+        --retries=<n>    request attempts before giving up
+        --file=<file>    play a local AAC/M4A recording
+        --station=<station> start on a station id or name, matched loosely
+        --area=<area>    only the stations of an area such as JP13
+        --crossfade=<ms> crossfade when switching stations
+        --eq=<preset>    flat, bass, laptop, talk, night, mono or 10 band gains
+        --sleep=<duration> stop after 45m, 1h30m, ... or at the end of the program
+        --alarm=<alarm>  HH:MM[,days][,station], ex: 06:30,mon-fri,TBS
+        --hook=<cmd>     run on program/station change, forbidden and reconnect
+        --notify         desktop notifications on the same events
+        --status-file=<path> keep the current state in a JSON file
     -h, --help           Prints help information
 ";
 
@@ -44,11 +91,231 @@ pub struct Options {
     #[bpaf(argument("proxy"))]
     /// ex: [http(s)|socks5]://<ip>:<port>
     pub proxy: Option<String>,
+    #[bpaf(argument("sec"))]
+    /// target latency behind live, defaults to 3 segments
+    pub latency: Option<u64>,
+    #[bpaf(argument("n"))]
+    /// request attempts before giving up
+    pub retries: Option<u32>,
+    #[bpaf(argument("file"))]
+    /// play a local AAC/M4A recording
+    pub file: Option<PathBuf>,
+    #[bpaf(argument("station"))]
+    /// start on a station, by id or a fuzzy name such as tbs or ぶんか
+    pub station: Option<String>,
+    #[bpaf(argument("area"))]
+    /// only the stations of an area such as JP13, instead of picking a region
+    pub area: Option<String>,
+    #[bpaf(argument("ms"))]
+    /// crossfade when switching stations, defaults to 2000
+    pub crossfade: Option<u64>,
+    #[bpaf(argument("preset"))]
+    /// flat, bass, laptop, talk, night, mono or 10 comma separated band gains in dB
+    pub eq: Option<String>,
+    #[bpaf(argument("duration"))]
+    /// stop after a duration such as 45m or 1h30m, or `end` for the end of the program
+    pub sleep: Option<Timer>,
+    #[bpaf(argument("alarm"), many)]
+    /// HH:MM[,days][,station], days as daily, weekdays, weekends, mon-fri or sat+sun
+    pub alarm: Vec<Alarm>,
+    #[bpaf(argument("cmd"), many)]
+    /// shell command run on events, details in RADICO_* variables and JSON on stdin
+    pub hook: Vec<String>,
+    #[bpaf(long, guard(notify_supported, "desktop notifications need D-Bus, which is not available here"))]
+    /// desktop notifications on events
+    pub notify: bool,
+    #[bpaf(argument("path"))]
+    /// keep the current state in a JSON file
+    pub status_file: Option<PathBuf>,
+    #[bpaf(external(cmd), optional)]
+    pub cmd: Option<Cmd>,
     #[bpaf(any("url", not_help))]
     /// url
     pub url: Option<String>,
 }
 
+#[derive(Debug, Clone, Bpaf)]
+pub enum Cmd {
+    #[bpaf(command("status"))]
+    /// print the state of the running instance as JSON
+    Status,
+    #[bpaf(command("next"))]
+    /// switch the running instance to the next station
+    Next,
+    #[bpaf(command("prev"))]
+    /// switch the running instance to the previous station
+    Prev,
+    #[bpaf(command("tune"))]
+    /// switch the running instance to a station id or name
+    Tune {
+        #[bpaf(positional("station"))]
+        station: String,
+    },
+    #[bpaf(command("volume"))]
+    /// set the volume of the running instance
+    Volume {
+        #[bpaf(positional("level"))]
+        level: u8,
+    },
+    #[bpaf(command("stop"))]
+    /// stop the running instance
+    Stop,
+    #[bpaf(command("play"))]
+    /// resume the running instance, or play a past program with --at
+    Play {
+        #[bpaf(argument("station"))]
+        /// station id or name
+        station: Option<String>,
+        #[bpaf(argument("time"))]
+        /// a time in the program, YYYY-MM-DDThh:mm in Japan time
+        at: Option<String>,
+        #[bpaf(positional("url"))]
+        url: Option<String>,
+    },
+    #[bpaf(command("eq"))]
+    /// set or cycle the EQ preset of the running instance
+    Eq {
+        #[bpaf(positional("preset"))]
+        preset: Option<String>,
+    },
+    #[bpaf(command("download"))]
+    /// save a past program to a file and exit
+    Download {
+        #[bpaf(argument("station"))]
+        /// station id or name
+        station: String,
+        #[bpaf(argument("time"))]
+        /// start, YYYY-MM-DDThh:mm in Japan time
+        from: String,
+        #[bpaf(argument("time"))]
+        /// end, defaults to the end of the program on air at --from
+        to: Option<String>,
+        #[bpaf(short, long, argument("file"))]
+        /// defaults to <station>_<YYYYMMDDhhmm>.aac
+        output: Option<PathBuf>,
+        #[bpaf(argument("n"))]
+        /// segments fetched at once, defaults to 4
+        jobs: Option<usize>,
+        #[bpaf(switch)]
+        /// embed the station logo in the tags
+        logo: bool,
+        #[bpaf(positional("url"))]
+        url: String,
+    },
+    #[bpaf(command("stations"))]
+    /// list stations and exit
+    Stations {
+        #[bpaf(argument("area"))]
+        /// only stations of an area such as JP13
+        area: Option<String>,
+        #[bpaf(external(format))]
+        format: Format,
+        #[bpaf(positional("url"))]
+        url: String,
+    },
+    #[bpaf(command("areas"))]
+    /// list areas and exit
+    Areas {
+        #[bpaf(external(format))]
+        format: Format,
+        #[bpaf(positional("url"))]
+        url: String,
+    },
+    #[bpaf(command("guide"))]
+    /// print the program guide of a station and exit
+    Guide {
+        #[bpaf(argument("date"))]
+        /// YYYYMMDD or YYYY-MM-DD, defaults to today's broadcast day
+        date: Option<String>,
+        #[bpaf(external(format))]
+        format: Format,
+        #[bpaf(positional("station"))]
+        station: String,
+        #[bpaf(positional("url"))]
+        url: String,
+    },
+    #[bpaf(command("search"))]
+    /// search the coming programs by keyword or performer and exit
+    Search {
+        #[bpaf(argument("n"))]
+        /// days ahead, defaults to 7
+        days: Option<u64>,
+        #[bpaf(argument("station"))]
+        /// only this station
+        station: Option<String>,
+        #[bpaf(argument("area"))]
+        /// stations of an area such as JP13, defaults to the detected one
+        area: Option<String>,
+        #[bpaf(argument("key"))]
+        /// schedule a match for `radico record` by its key, such as
+        /// TBS@20261017210000
+        record: Option<String>,
+        #[bpaf(external(format))]
+        format: Format,
+        #[bpaf(positional("query"))]
+        query: String,
+        #[bpaf(positional("url"))]
+        url: String,
+    },
+    #[bpaf(command("record"))]
+    /// save the scheduled programs that have aired and exit
+    Record {
+        #[bpaf(switch)]
+        /// keep running, saving each program as it airs
+        wait: bool,
+        #[bpaf(switch)]
+        /// print the schedule
+        list: bool,
+        #[bpaf(external(format))]
+        format: Format,
+        #[bpaf(short, long, argument("dir"))]
+        /// defaults to the current directory
+        output: Option<PathBuf>,
+        #[bpaf(positional("url"))]
+        url: String,
+    },
+}
+
+/// output of the listing commands
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+fn format() -> impl Parser<Format> {
+    let json = long("json").help("print JSON").req_flag(Format::Json);
+    let csv = long("csv").help("print CSV").req_flag(Format::Csv);
+    construct!([json, csv]).fallback(Format::Table)
+}
+
+impl Cmd {
+    /// the control socket request, none for the commands that run on their own
+    pub fn request(&self) -> Option<String> {
+        let req = match self {
+            Cmd::Status => "status".to_string(),
+            Cmd::Next => "next".to_string(),
+            Cmd::Prev => "prev".to_string(),
+            Cmd::Tune { station } => format!("tune {}", station),
+            Cmd::Volume { level } => format!("volume {}", level),
+            Cmd::Stop => "stop".to_string(),
+            Cmd::Play { station: None, at: None, .. } => "play".to_string(),
+            Cmd::Eq { preset: Some(preset) } => format!("eq {}", preset),
+            Cmd::Eq { preset: None } => "eq".to_string(),
+            // --station goes with --at, which main checks
+            Cmd::Play { .. }
+            | Cmd::Download { .. }
+            | Cmd::Stations { .. }
+            | Cmd::Areas { .. }
+            | Cmd::Guide { .. }
+            | Cmd::Search { .. }
+            | Cmd::Record { .. } => return None,
+        };
+        Some(req)
+    }
+}
+
 fn verbose() -> impl Parser<usize> {
     // number of occurrences of the v/verbose flag capped at 3
     short('v')
@@ -60,6 +327,11 @@ fn verbose() -> impl Parser<usize> {
         .guard(|&x| x <= 3, "It doesn't get any more verbose than this")
 }
 
+/// notifications go over D-Bus, there is nothing to send them to elsewhere
+fn notify_supported(notify: &bool) -> bool {
+    cfg!(unix) || !notify
+}
+
 pub fn about() -> &'static str {
     ABOUT
 }
@@ -76,8 +348,10 @@ fn not_help(s: String) -> Option<String> {
     }
 }
 
+static OPTIONS: LazyLock<Options> = LazyLock::new(|| options().run());
+
 impl Options {
     pub fn init() -> Options {
-        options().run()
+        OPTIONS.clone()
     }
 }
@@ -1,5 +1,5 @@
 use colored::Colorize;
-use crossterm::terminal::{disable_raw_mode, Clear, ClearType};
+use crossterm::terminal::{disable_raw_mode, size, Clear, ClearType};
 use crossterm::{cursor, execute};
 use std::fmt::Display;
 use std::{io, process};
@@ -7,6 +7,7 @@ use anyhow::Error;
 
 
 pub mod args;
+pub mod table;
 
 #[allow(dead_code)]
 pub fn init() {
@@ -29,9 +30,14 @@ pub(crate) fn clear_screen() {
     .unwrap();
 }
 
-pub fn quit(_e: Error) -> ! {
+/// leave raw mode and show the cursor again
+pub fn restore() {
     disable_raw_mode().unwrap();
     execute!(io::stdout(), cursor::Show).unwrap();
+}
+
+pub fn quit(_e: Error) -> ! {
+    restore();
     #[cfg(windows)]
     asio_kill();
 
@@ -75,6 +81,24 @@ pub fn print_warn(error: impl Display) {
     println!("{} {}\r", "WARN:".bright_yellow(), error);
 }
 
+pub fn print_info(info: impl Display) {
+    println!("{} {}\r", "INFO:".bright_green(), info);
+}
+
+/// single line at the bottom of the screen, empty to clear it
+pub fn print_status(status: impl Display) {
+    let (_, rows) = size().unwrap_or((80, 24));
+    execute!(
+        io::stdout(),
+        cursor::SavePosition,
+        cursor::MoveTo(0, rows.saturating_sub(1)),
+        Clear(ClearType::CurrentLine)
+    )
+    .unwrap();
+    print!("{}", status.to_string().bright_black());
+    execute!(io::stdout(), cursor::RestorePosition).unwrap();
+}
+
 pub struct Quit;
 impl Drop for Quit {
     fn drop(&mut self) {
@@ -0,0 +1,74 @@
+use crate::terminal::args::Format;
+use serde_json::{Map, Value};
+use unicode_width::UnicodeWidthStr;
+
+const GAP: usize = 2;
+
+/// print rows under a header as an aligned table, JSON objects keyed by the
+/// header, or CSV
+pub fn print(format: Format, header: &[&str], rows: &[Vec<String>]) {
+    match format {
+        Format::Table => print!("{}", table(header, rows)),
+        Format::Json => println!("{}", json(header, rows)),
+        Format::Csv => print!("{}", csv(header, rows)),
+    }
+}
+
+fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths = header.iter().map(|x| x.width()).collect::<Vec<_>>();
+    for row in rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.width());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let mut s = String::new();
+        for (i, cell) in cells.iter().enumerate() {
+            s.push_str(cell);
+            if i + 1 < cells.len() {
+                s.push_str(&" ".repeat(widths[i] - cell.width() + GAP));
+            }
+        }
+        s.push('\n');
+        s
+    };
+
+    let mut out = line(header.to_vec());
+    for row in rows {
+        out.push_str(&line(row.iter().map(String::as_str).collect()));
+    }
+    out
+}
+
+fn json(header: &[&str], rows: &[Vec<String>]) -> String {
+    let v = rows
+        .iter()
+        .map(|row| {
+            header
+                .iter()
+                .zip(row)
+                .map(|(k, v)| (k.to_string(), Value::String(v.to_owned())))
+                .collect::<Map<_, _>>()
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string_pretty(&v).unwrap_or_default()
+}
+
+/// RFC 4180, quoting only where needed
+fn csv(header: &[&str], rows: &[Vec<String>]) -> String {
+    let field = |x: &str| {
+        if x.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", x.replace('"', "\"\""))
+        } else {
+            x.to_string()
+        }
+    };
+    let mut out = header.iter().map(|x| field(x)).collect::<Vec<_>>().join(",");
+    out.push_str("\r\n");
+    for row in rows {
+        out.push_str(&row.iter().map(|x| field(x)).collect::<Vec<_>>().join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
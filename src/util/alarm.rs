@@ -0,0 +1,227 @@
+use crate::errors::RadicoError::AlarmError;
+use crate::util::store;
+use anyhow::{Error, Result};
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Weekday};
+use log::info;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const SNOOZE: Duration = Duration::from_secs(9 * 60);
+/// volume rises from 1 to the chosen level over this time
+const RAMP: Duration = Duration::from_secs(60);
+/// alarms kept between sessions, an array of `--alarm` specs
+const ALARM_FILE: &str = "alarms.json";
+
+/// `HH:MM[,days][,station]`, days being `daily`, `weekdays`, `weekends`, a
+/// range like `mon-fri` or a list like `sat+sun`, station an id or name
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Alarm {
+    pub time: NaiveTime,
+    /// empty for every day
+    pub days: Vec<Weekday>,
+    pub station: Option<String>,
+}
+
+impl Alarm {
+    /// the first time this alarm goes off after `now`
+    fn next_after(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=7)
+            .filter_map(|x| now.date().checked_add_days(chrono::Days::new(x)))
+            .map(|x| x.and_time(self.time))
+            .find(|x| *x > now && (self.days.is_empty() || self.days.contains(&x.weekday())))
+    }
+}
+
+fn parse_days(s: &str) -> Option<Vec<Weekday>> {
+    let week = |from: Weekday, to: Weekday| {
+        let mut days = vec![from];
+        while *days.last()? != to {
+            days.push(days.last()?.succ());
+        }
+        Some(days)
+    };
+    match s {
+        "daily" | "*" => Some(Vec::new()),
+        "weekdays" => week(Weekday::Mon, Weekday::Fri),
+        "weekends" => week(Weekday::Sat, Weekday::Sun),
+        _ => s
+            .split('+')
+            .map(|x| match x.split_once('-') {
+                Some((from, to)) => week(from.parse().ok()?, to.parse().ok()?),
+                None => Some(vec![x.parse().ok()?]),
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|x| x.concat()),
+    }
+}
+
+impl FromStr for Alarm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut fields = s.split(',').map(|x| x.trim());
+        let time = fields
+            .next()
+            .and_then(|x| NaiveTime::parse_from_str(x, "%H:%M").ok())
+            .ok_or(AlarmError(s.to_string()))?;
+        let mut alarm = Alarm {
+            time,
+            days: Vec::new(),
+            station: None,
+        };
+        for field in fields.filter(|x| !x.is_empty()) {
+            match parse_days(&field.to_lowercase()) {
+                Some(days) if alarm.days.is_empty() && alarm.station.is_none() => alarm.days = days,
+                _ if alarm.station.is_none() => alarm.station = Some(field.to_string()),
+                _ => return Err(Error::from(AlarmError(s.to_string()))),
+            }
+        }
+        Ok(alarm)
+    }
+}
+
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.time.format("%H:%M"))?;
+        if !self.days.is_empty() {
+            let days = self.days.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            write!(f, " {}", days.join("+"))?;
+        }
+        if let Some(station) = &self.station {
+            write!(f, " {}", station)?;
+        }
+        Ok(())
+    }
+}
+
+pub enum Event {
+    /// tune in, to the alarm's station if it has one
+    Ring(Option<String>),
+    /// next step of the volume ramp
+    Volume(char),
+}
+
+struct Ramp {
+    since: Instant,
+    level: char,
+    target: char,
+}
+
+#[derive(Default)]
+pub struct AlarmClock {
+    alarms: Vec<Alarm>,
+    /// the alarm ringing or snoozed
+    active: Option<usize>,
+    snooze: Option<NaiveDateTime>,
+    ramp: Option<Ramp>,
+    last: Option<NaiveDateTime>,
+}
+
+impl AlarmClock {
+    /// alarms from the command line and the alarm file
+    pub fn new(alarms: Vec<Alarm>) -> Self {
+        let mut alarms = alarms;
+        let saved: Vec<String> = store::load(ALARM_FILE);
+        for spec in saved {
+            match spec.parse() {
+                Ok(alarm) if !alarms.contains(&alarm) => alarms.push(alarm),
+                Ok(_) => {},
+                Err(e) => info!("{}: {:?}\r", ALARM_FILE, e),
+            }
+        }
+        AlarmClock {
+            alarms,
+            last: Some(Local::now().naive_local()),
+            ..Default::default()
+        }
+    }
+
+    /// the next alarm and when it goes off
+    fn next(&self, now: NaiveDateTime) -> Option<(NaiveDateTime, usize)> {
+        self.alarms
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| Some((x.next_after(now)?, i)))
+            .min()
+    }
+
+    /// check the clock, `volume` being the level to ramp up to
+    pub fn poll(&mut self, volume: char) -> Option<Event> {
+        let now = Local::now().naive_local();
+        let last = self.last.replace(now)?;
+
+        let due = match self.snooze {
+            Some(at) if at <= now => self.snooze.take().and(self.active),
+            Some(_) => None,
+            None => self.next(last).filter(|(at, _)| *at <= now).map(|(_, i)| i),
+        };
+        if let Some(i) = due {
+            info!("alarm {}\r", self.alarms[i]);
+            self.active = Some(i);
+            self.ramp = Some(Ramp {
+                since: Instant::now(),
+                level: '0',
+                target: volume,
+            });
+            return Some(Event::Ring(self.alarms[i].station.clone()));
+        }
+
+        let ramp = self.ramp.as_mut()?;
+        let target = ramp.target.to_digit(10)?.max(1);
+        let progress = ramp.since.elapsed().as_secs_f32() / RAMP.as_secs_f32();
+        if progress >= 1.0 {
+            self.ramp = None;
+            return Some(Event::Volume(char::from_digit(target, 10)?));
+        }
+        let level = char::from_digit(1 + ((target - 1) as f32 * progress) as u32, 10)?;
+        if level == ramp.level {
+            return None;
+        }
+        ramp.level = level;
+        Some(Event::Volume(level))
+    }
+
+    /// leave the volume to the user
+    pub fn cancel_ramp(&mut self) {
+        self.ramp = None;
+    }
+
+    /// snooze the ringing alarm, false if none is
+    pub fn snooze(&mut self) -> bool {
+        if self.active.is_none() || self.snooze.is_some() {
+            return false;
+        }
+        self.ramp = None;
+        self.snooze = Some(Local::now().naive_local() + SNOOZE);
+        true
+    }
+
+    /// dismiss the ringing or snoozed alarm, false if none is
+    pub fn stop(&mut self) -> bool {
+        self.ramp = None;
+        self.snooze = None;
+        self.active.take().is_some()
+    }
+
+    /// status line text
+    pub fn status(&self) -> String {
+        let now = Local::now().naive_local();
+        if let Some(at) = self.snooze {
+            return format!("snoozed until {}", at.format("%H:%M"));
+        }
+        if self.active.is_some() {
+            return String::from("alarm: z snooze, x stop");
+        }
+        match self.next(now) {
+            Some((at, i)) => format!(
+                "alarm {} {}",
+                at.format("%a %H:%M"),
+                self.alarms[i].station.as_deref().unwrap_or_default()
+            )
+            .trim_end()
+            .to_string(),
+            None => String::new(),
+        }
+    }
+}
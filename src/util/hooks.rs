@@ -0,0 +1,212 @@
+use log::{error, info};
+use serde::Serialize;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// hooks still running after this are killed
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+const NOTIFY_TIMEOUT_MS: i32 = 5000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    ProgramChange,
+    StationChange,
+    Forbidden,
+    Reconnect,
+}
+
+impl Kind {
+    /// the name in the JSON, for `RADICO_EVENT`
+    fn name(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|x| x.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+}
+
+/// passed to hooks as JSON on stdin and as `RADICO_*` environment variables
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub event: Kind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub station: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub station_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// program start and end, YYYYMMDDhhmmss
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<String>,
+    /// seconds offline before a reconnect
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downtime: Option<u64>,
+}
+
+impl Event {
+    pub fn new(event: Kind) -> Self {
+        Event {
+            event,
+            station: None,
+            station_id: None,
+            title: None,
+            start: None,
+            end: None,
+            info: None,
+            downtime: None,
+        }
+    }
+
+    pub fn station(mut self, name: Option<&str>, id: Option<&str>) -> Self {
+        self.station = name.map(str::to_string);
+        self.station_id = id.map(str::to_string);
+        self
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        [
+            ("RADICO_EVENT", Some(self.event.name())),
+            ("RADICO_STATION", self.station.clone()),
+            ("RADICO_STATION_ID", self.station_id.clone()),
+            ("RADICO_TITLE", self.title.clone()),
+            ("RADICO_START", self.start.clone()),
+            ("RADICO_END", self.end.clone()),
+            ("RADICO_INFO", self.info.clone()),
+            ("RADICO_DOWNTIME", self.downtime.map(|x| x.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| Some((k, v?)))
+        .collect()
+    }
+
+    /// notification summary and body
+    fn notification(&self) -> (String, String) {
+        let station = self.station.clone().unwrap_or_else(|| "radico".to_string());
+        let time = |x: &Option<String>| {
+            x.as_deref()
+                .and_then(|x| x.get(8..12))
+                .map(|x| format!("{}:{}", &x[..2], &x[2..]))
+        };
+        let body = match self.event {
+            Kind::ProgramChange => format!(
+                "{}-{} {}",
+                time(&self.start).unwrap_or_default(),
+                time(&self.end).unwrap_or_default(),
+                self.title.as_deref().unwrap_or_default()
+            ),
+            Kind::StationChange => "tuned in".to_string(),
+            Kind::Forbidden => "stream forbidden".to_string(),
+            Kind::Reconnect => format!("reconnected after {}s", self.downtime.unwrap_or_default()),
+        };
+        (station, body)
+    }
+}
+
+struct Hooks {
+    commands: Vec<String>,
+    notify: bool,
+}
+
+static HOOKS: OnceLock<Hooks> = OnceLock::new();
+
+/// the `--hook` commands and `--notify`, set once at startup
+pub fn init(commands: Vec<String>, notify: bool) {
+    let _ = HOOKS.set(Hooks { commands, notify });
+}
+
+/// run the hooks and send the notification for an event in the background,
+/// nothing until `init`
+pub fn emit(event: Event) {
+    let Some(hooks) = HOOKS.get().filter(|x| !x.commands.is_empty() || x.notify) else {
+        return;
+    };
+    info!("hook {:?}\r", event);
+    tokio::spawn(async move {
+        for cmd in &hooks.commands {
+            if let Err(e) = run(cmd, &event).await {
+                error!("hook {}: {:?}\r", cmd, e);
+            }
+        }
+        if hooks.notify {
+            if let Err(e) = notify(&event).await {
+                error!("notify: {:?}\r", e);
+            }
+        }
+    });
+}
+
+async fn run(cmd: &str, event: &Event) -> anyhow::Result<()> {
+    #[cfg(windows)]
+    let mut command = Command::new("cmd");
+    #[cfg(windows)]
+    command.arg("/C");
+    #[cfg(not(windows))]
+    let mut command = Command::new("sh");
+    #[cfg(not(windows))]
+    command.arg("-c");
+
+    let mut child = command
+        .arg(cmd)
+        .envs(event.env())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the hook may not read stdin at all
+        let _ = stdin.write_all(&serde_json::to_vec(event)?).await;
+    }
+    let status = tokio::time::timeout(HOOK_TIMEOUT, child.wait()).await??;
+    if !status.success() {
+        info!("hook {} exited with {}\r", cmd, status);
+    }
+    Ok(())
+}
+
+/// the session bus, connected on the first notification
+#[cfg(unix)]
+static BUS: tokio::sync::OnceCell<zbus::Connection> = tokio::sync::OnceCell::const_new();
+
+/// org.freedesktop.Notifications.Notify over the session bus
+#[cfg(unix)]
+async fn notify(event: &Event) -> anyhow::Result<()> {
+    use std::collections::HashMap;
+    use zbus::zvariant::Value;
+
+    let (summary, body) = event.notification();
+    let bus = BUS.get_or_try_init(zbus::Connection::session).await?;
+    bus.call_method(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        Some("org.freedesktop.Notifications"),
+        "Notify",
+        // app name, replaces id, icon, summary, body, actions, hints, timeout
+        &(
+            "radico",
+            0u32,
+            "",
+            summary.as_str(),
+            body.as_str(),
+            Vec::<&str>::new(),
+            HashMap::<&str, Value>::new(),
+            NOTIFY_TIMEOUT_MS,
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// never called, `--notify` is rejected where there is no session bus
+#[cfg(not(unix))]
+async fn notify(_event: &Event) -> anyhow::Result<()> {
+    Ok(())
+}
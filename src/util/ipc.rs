@@ -0,0 +1,170 @@
+use crate::api::command::Request;
+#[cfg(unix)]
+use crate::api::command::Command;
+use crate::util::status::NowPlaying;
+use anyhow::Result;
+#[cfg(unix)]
+use anyhow::Context;
+#[cfg(unix)]
+use log::{error, info};
+use std::env;
+#[cfg(unix)]
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+#[cfg(unix)]
+use std::sync::OnceLock;
+#[cfg(unix)]
+use std::time::Duration;
+#[cfg(unix)]
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use tokio::sync::oneshot;
+use tokio::sync::mpsc;
+
+/// a request waiting longer than this for its answer gives up
+#[cfg(unix)]
+const REPLY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// `$XDG_RUNTIME_DIR/radico.sock`, or a per-user name in the temp directory
+pub fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("radico.sock"),
+        #[cfg(unix)]
+        None => env::temp_dir().join(format!("radico-{}.sock", unsafe { libc::getuid() })),
+        #[cfg(not(unix))]
+        None => env::temp_dir().join("radico.sock"),
+    }
+}
+
+/// the control socket, held with the lock that makes it ours
+#[cfg(unix)]
+pub struct Listener {
+    socket: UnixListener,
+    _lock: File,
+}
+
+/// where the worker takes requests, once it does
+#[cfg(unix)]
+static WORKER: OnceLock<(Arc<Mutex<NowPlaying>>, mpsc::Sender<Request>)> = OnceLock::new();
+
+/// take the control socket, none if another instance owns it; the lock
+/// next to it keeps two instances starting together from unlinking each
+/// other's socket
+#[cfg(unix)]
+pub async fn bind() -> Result<Option<Listener>> {
+    use std::os::fd::AsRawFd;
+
+    let path = socket_path();
+    let lock = File::create(path.with_extension("lock"))?;
+    if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let e = std::io::Error::last_os_error();
+        if e.kind() == std::io::ErrorKind::WouldBlock {
+            return Ok(None);
+        }
+        return Err(e.into());
+    }
+    // left over from an instance that did not exit cleanly
+    let _ = std::fs::remove_file(&path);
+    let socket = UnixListener::bind(&path)?;
+    info!("listening on {:?}\r", path);
+    Ok(Some(Listener { socket, _lock: lock }))
+}
+
+/// answer requests from other radico processes, one line each way; until
+/// the worker is attached they are told it is not ready
+#[cfg(unix)]
+pub fn serve(listener: Listener) {
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.socket.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    error!("accept: {:?}\r", e);
+                    continue;
+                },
+            };
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream).await {
+                    error!("ipc: {:?}\r", e);
+                }
+            });
+        }
+    });
+}
+
+/// hand the status and player commands of the worker to the socket
+#[cfg(unix)]
+pub fn attach(now: Arc<Mutex<NowPlaying>>, tx: mpsc::Sender<Request>) {
+    let _ = WORKER.set((now, tx));
+}
+
+#[cfg(unix)]
+async fn handle(stream: UnixStream) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        let Some((now, tx)) = WORKER.get() else {
+            let res = serde_json::json!({ "error": "radico is starting, not ready yet" });
+            write.write_all(format!("{}\n", res).as_bytes()).await?;
+            continue;
+        };
+        let res = match line.trim() {
+            "status" => serde_json::to_string(&*now.lock().unwrap())?,
+            line => {
+                let res = match line.parse::<Command>() {
+                    Ok(cmd) => {
+                        let (reply, rx) = oneshot::channel();
+                        tx.send(Request { cmd, reply: Some(reply) }).await?;
+                        rx.await?
+                    },
+                    Err(e) => Err(e.to_string()),
+                };
+                match res {
+                    Ok(msg) => serde_json::json!({ "ok": msg }),
+                    Err(e) => serde_json::json!({ "error": e }),
+                }
+                .to_string()
+            },
+        };
+        write.write_all(format!("{}\n", res).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// send one request to the running instance and return its answer
+#[cfg(unix)]
+pub async fn request(cmd: &str) -> Result<String> {
+    let stream = UnixStream::connect(socket_path())
+        .await
+        .context("radico is not running")?;
+    let (read, mut write) = stream.into_split();
+    write.write_all(format!("{}\n", cmd).as_bytes()).await?;
+    write.shutdown().await?;
+    let mut lines = BufReader::new(read).lines();
+    let line = tokio::time::timeout(REPLY_TIMEOUT, lines.next_line())
+        .await
+        .context("radico did not answer")??;
+    Ok(line.unwrap_or_default())
+}
+
+#[cfg(not(unix))]
+pub struct Listener;
+
+#[cfg(not(unix))]
+pub async fn bind() -> Result<Option<Listener>> {
+    Ok(Some(Listener))
+}
+
+#[cfg(not(unix))]
+pub fn serve(_listener: Listener) {}
+
+#[cfg(not(unix))]
+pub fn attach(_now: Arc<Mutex<NowPlaying>>, _tx: mpsc::Sender<Request>) {}
+
+#[cfg(not(unix))]
+pub async fn request(_cmd: &str) -> Result<String> {
+    Err(anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::Unsupported)))
+}
@@ -6,9 +6,16 @@ use crossterm::terminal::{
 use crossterm::{cursor, execute};
 use inquire::ui::{Attributes, Color, RenderConfig, StyleSheet, Styled};
 use inquire::Select;
+use crate::util::search;
 use std::io;
 
 pub fn show(v: &[String]) -> Result<String> {
+    let i = pick("station?", v, 0)?;
+    Ok(v[i].to_owned())
+}
+
+/// pick one of the options, starting at `start`, returns its index
+pub fn pick(prompt: &str, v: &[String], start: usize) -> Result<usize> {
     inquire::set_global_render_config(render_config());
 
     let mut stdout = io::stdout();
@@ -22,8 +29,12 @@ pub fn show(v: &[String]) -> Result<String> {
         cursor::Hide
     )?;
 
-    let station = match Select::new("station?", v.to_vec()).prompt() {
-        Ok(station) => station,
+    let option = match Select::new(prompt, v.to_vec())
+        .with_scorer(&search::scorer)
+        .with_starting_cursor(start)
+        .raw_prompt()
+    {
+        Ok(option) => option,
         Err(e) => return Err(Error::from(e)),
     };
 
@@ -33,7 +44,7 @@ pub fn show(v: &[String]) -> Result<String> {
         cursor::Show
     )?;
 
-    Ok(station)
+    Ok(option.index)
 }
 
 pub fn render_config() -> RenderConfig<'static> {
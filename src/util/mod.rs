@@ -1,4 +1,12 @@
+pub mod alarm;
+pub mod hooks;
+pub mod ipc;
 pub mod macros;
 pub mod menu;
+pub mod search;
 pub mod sleep;
 pub mod state;
+pub mod status;
+pub mod store;
+pub mod tags;
+pub mod timer;
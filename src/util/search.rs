@@ -0,0 +1,275 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// kunrei romaji of ぁ (U+3041) through ゖ (U+3096)
+const KANA: [&str; 86] = [
+    "a", "a", "i", "i", "u", "u", "e", "e", "o", "o", // ぁ - お
+    "ka", "ga", "ki", "gi", "ku", "gu", "ke", "ge", "ko", "go", // か - ご
+    "sa", "za", "si", "zi", "su", "zu", "se", "ze", "so", "zo", // さ - ぞ
+    "ta", "da", "ti", "zi", "", "tu", "zu", "te", "de", "to", "do", // た - ど
+    "na", "ni", "nu", "ne", "no", // な - の
+    "ha", "ba", "pa", "hi", "bi", "pi", "hu", "bu", "pu", // は - ぷ
+    "he", "be", "pe", "ho", "bo", "po", // へ - ぽ
+    "ma", "mi", "mu", "me", "mo", // ま - も
+    "ya", "ya", "yu", "yu", "yo", "yo", // ゃ - よ
+    "ra", "ri", "ru", "re", "ro", // ら - ろ
+    "wa", "wa", "i", "e", "o", "n", "bu", "ka", "ke", // ゎ - ゖ
+];
+
+/// readings of the kanji that turn up in station names, longest first where
+/// they overlap
+const READINGS: &[(&str, &str)] = &[
+    ("西日本", "にしにっぽん"),
+    ("日本", "にっぽん"),
+    ("放送", "ほうそう"),
+    ("文化", "ぶんか"),
+    ("第", "だい"),
+    ("東海", "とうかい"),
+    ("関西", "かんさい"),
+    ("九州", "きゅうしゅう"),
+    ("四国", "しこく"),
+    ("中国", "ちゅうごく"),
+    ("北陸", "ほくりく"),
+    ("山陰", "さんいん"),
+    ("信越", "しんえつ"),
+    ("中部", "ちゅうぶ"),
+    ("南海", "なんかい"),
+    ("琉球", "りゅうきゅう"),
+    ("北海道", "ほっかいどう"),
+    ("青森", "あおもり"),
+    ("岩手", "いわて"),
+    ("宮城", "みやぎ"),
+    ("秋田", "あきた"),
+    ("山形", "やまがた"),
+    ("福島", "ふくしま"),
+    ("茨城", "いばらき"),
+    ("栃木", "とちぎ"),
+    ("群馬", "ぐんま"),
+    ("埼玉", "さいたま"),
+    ("千葉", "ちば"),
+    ("東京", "とうきょう"),
+    ("神奈川", "かながわ"),
+    ("新潟", "にいがた"),
+    ("富山", "とやま"),
+    ("石川", "いしかわ"),
+    ("福井", "ふくい"),
+    ("山梨", "やまなし"),
+    ("長野", "ながの"),
+    ("岐阜", "ぎふ"),
+    ("静岡", "しずおか"),
+    ("愛知", "あいち"),
+    ("三重", "みえ"),
+    ("滋賀", "しが"),
+    ("京都", "きょうと"),
+    ("大阪", "おおさか"),
+    ("兵庫", "ひょうご"),
+    ("奈良", "なら"),
+    ("和歌山", "わかやま"),
+    ("鳥取", "とっとり"),
+    ("島根", "しまね"),
+    ("岡山", "おかやま"),
+    ("広島", "ひろしま"),
+    ("山口", "やまぐち"),
+    ("徳島", "とくしま"),
+    ("香川", "かがわ"),
+    ("愛媛", "えひめ"),
+    ("高知", "こうち"),
+    ("福岡", "ふくおか"),
+    ("佐賀", "さが"),
+    ("長崎", "ながさき"),
+    ("熊本", "くまもと"),
+    ("大分", "おおいた"),
+    ("宮崎", "みやざき"),
+    ("鹿児島", "かごしま"),
+    ("沖縄", "おきなわ"),
+    ("関東", "かんとう"),
+];
+
+/// hepburn and long vowel spellings folded onto one form, in order
+const FOLD: &[(&str, &str)] = &[
+    ("tch", "tt"),
+    ("shi", "si"),
+    ("sh", "sy"),
+    ("chi", "ti"),
+    ("ch", "ty"),
+    ("tsu", "tu"),
+    ("ji", "zi"),
+    ("j", "zy"),
+    ("di", "zi"),
+    ("du", "zu"),
+    ("fu", "hu"),
+    ("f", "h"),
+    ("v", "b"),
+    ("ou", "o"),
+    ("oo", "o"),
+    ("uu", "u"),
+    ("aa", "a"),
+    ("ii", "i"),
+    ("ee", "e"),
+    ("nn", "n"),
+];
+
+fn is_small(c: char) -> bool {
+    matches!(c, 'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ' | 'ゃ' | 'ゅ' | 'ょ' | 'ゎ')
+}
+
+/// hiragana and katakana to romaji, anything else kept as is
+fn romaji(s: &str) -> String {
+    let mut out = String::new();
+    let mut double = false;
+    for c in s.chars() {
+        // katakana to hiragana
+        let c = match c {
+            'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+            _ => c,
+        };
+        let r = match c {
+            'っ' => {
+                double = true;
+                continue;
+            },
+            'ぁ'..='ゖ' => KANA[(c as u32 - 'ぁ' as u32) as usize],
+            // prolonged sound mark
+            'ー' => continue,
+            _ => {
+                out.push(c);
+                continue;
+            },
+        };
+        if is_small(c) && out.ends_with(['a', 'i', 'u', 'e', 'o']) {
+            // きゃ kya, ふぁ fa
+            out.pop();
+            if !r.starts_with('y') {
+                out.push_str(r);
+                continue;
+            }
+        }
+        if double {
+            out.push_str(&r[..1]);
+            double = false;
+        }
+        out.push_str(r);
+    }
+    out
+}
+
+/// a search key: half width, lower case, kana and known kanji as romaji,
+/// spellings folded and punctuation dropped
+pub fn normalize(s: &str) -> String {
+    let mut s = s.nfkc().collect::<String>().to_lowercase();
+    for (kanji, kana) in READINGS {
+        s = s.replace(kanji, kana);
+    }
+    let mut s = romaji(&s)
+        .chars()
+        .filter(|x| x.is_alphanumeric())
+        .collect::<String>();
+    for (from, to) in FOLD {
+        s = s.replace(from, to);
+    }
+    s
+}
+
+/// how well a query matches any of the keys, higher is better, none if it
+/// does not match at all
+pub fn score(query: &str, keys: &[&str]) -> Option<i64> { rank(query, keys, true) }
+
+/// `spread` also takes the letters of the query apart in a key, enough to
+/// narrow a list as it is typed but not to pick one item on its own
+fn rank(query: &str, keys: &[&str], spread: bool) -> Option<i64> {
+    let q = normalize(query);
+    if q.is_empty() {
+        return Some(0);
+    }
+    keys.iter().filter_map(|x| score_key(&q, &normalize(x), spread)).max()
+}
+
+fn score_key(q: &str, key: &str, spread: bool) -> Option<i64> {
+    let rest = key.len() as i64 - q.len() as i64;
+    if key == q {
+        return Some(3000);
+    }
+    if key.starts_with(q) {
+        return Some(2000 - rest);
+    }
+    if let Some(pos) = key.find(q) {
+        return Some(1000 - pos as i64 - rest);
+    }
+    if !spread {
+        return None;
+    }
+
+    // in order with gaps, the fewer and shorter the better
+    let mut chars = key.char_indices();
+    let mut last = None;
+    let mut gaps = 0;
+    for c in q.chars() {
+        let (i, _) = chars.find(|(_, x)| *x == c)?;
+        if last.is_some_and(|x| x + 1 != i) {
+            gaps += 1;
+        }
+        last = Some(i);
+    }
+    Some(500 - gaps * 10 - rest)
+}
+
+/// the best match among items, each searched by a few keys; the query has to
+/// be found whole in a key, and one with nothing to search for matches none
+pub fn best<'a, T>(query: &str, items: &'a [T], keys: impl Fn(&T) -> Vec<&str>) -> Option<&'a T> {
+    if normalize(query).is_empty() {
+        return None;
+    }
+    items
+        .iter()
+        .filter_map(|x| Some((rank(query, &keys(x), false)?, x)))
+        .rev()
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, x)| x)
+}
+
+/// inquire scorer for a list of names
+pub fn scorer<T>(input: &str, _: &T, name: &str, _: usize) -> Option<i64> {
+    score(input, &[name])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATIONS: [(&str, &str); 4] = [
+        ("TBS", "TBSラジオ"),
+        ("QRR", "文化放送"),
+        ("LFR", "ニッポン放送"),
+        ("JORF", "ラジオ日本"),
+    ];
+
+    fn find(query: &str) -> Option<&str> {
+        best(query, &STATIONS, |x| vec![x.0, x.1]).map(|x| x.0)
+    }
+
+    #[test]
+    fn whole() {
+        assert_eq!(find("tbs"), Some("TBS"));
+        assert_eq!(find("bunka"), Some("QRR"));
+        assert_eq!(find("ニッポン"), Some("LFR"));
+        assert_eq!(find("nippon"), Some("LFR"));
+        // a prefix before a match further in
+        assert_eq!(find("radio"), Some("JORF"));
+        assert_eq!(find("radio nippon"), Some("JORF"));
+        assert_eq!(find("nippon radio"), None);
+        // nothing left to search for once punctuation is dropped
+        assert_eq!(find(""), None);
+        assert_eq!(find("・"), None);
+        assert_eq!(find("-"), None);
+    }
+
+    #[test]
+    fn spread() {
+        // letters of "nippon hoso" in order, fine for a list being typed
+        assert!(score("nhs", &["ニッポン放送"]).is_some());
+        assert_eq!(find("nhs"), None);
+        assert_eq!(find("xyz"), None);
+        // an empty query leaves a list whole while typing, but picks nothing
+        assert_eq!(score("・", &["ニッポン放送"]), Some(0));
+        assert_eq!(score("-", &["TBSラジオ"]), Some(0));
+    }
+}
@@ -1,34 +1,80 @@
 use crate::errors::RadicoError::NegativeTime;
 use crate::terminal;
 use anyhow::Error;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-#[derive(Default)]
 pub struct StateCollector {
+    /// (bytes, milliseconds) of the last segments handed to the player
     v: Box<[(i64, i64); 4]>,
+    /// age of the newest segment when it was handed to the player
+    age: (i64, Instant),
+    /// playlist target duration
+    target: Duration,
+    /// configured target latency
+    latency: Option<Duration>,
+}
+
+impl Default for StateCollector {
+    fn default() -> Self {
+        Self::new(None)
+    }
 }
 
 impl StateCollector {
-    pub fn add(&mut self, a: i64, b: i64) {
-        if b < 0 {
-            terminal::print_warn(Error::from(NegativeTime(b)));
+    /// `latency` overrides the buffer target of three segments
+    pub fn new(latency: Option<Duration>) -> Self {
+        Self {
+            v: Default::default(),
+            age: (0, Instant::now()),
+            target: Duration::from_secs(5),
+            latency,
+        }
+    }
+
+    pub fn add(&mut self, bytes: i64, ms: i64, age: i64) {
+        if age < 0 {
+            terminal::print_warn(Error::from(NegativeTime(age)));
         }
         self.v.rotate_left(1);
         let l = self.v.len() - 1;
-        let _ = std::mem::replace(&mut self.v[l], (a, b));
+        let _ = std::mem::replace(&mut self.v[l], (bytes, ms));
+        self.age = (age, Instant::now());
+    }
+
+    pub fn set_target(&mut self, target: Duration) {
+        self.target = target;
+    }
+
+    /// audio to keep in the player, at least two segments
+    pub fn buffer_target(&self) -> Duration {
+        let (_, ms): (Vec<i64>, Vec<i64>) = self.v.iter().cloned().unzip();
+        let segment = Duration::from_millis((ms.iter().max().unwrap_or(&0) * 2) as u64);
+        self.latency.unwrap_or(self.target * 3).max(segment)
+    }
+
+    /// playback time of `len` buffered bytes
+    pub fn buffered(&self, len: usize) -> Duration {
+        let (bytes, ms): (Vec<i64>, Vec<i64>) = self.v.iter().cloned().unzip();
+        let (bytes, ms) = (bytes.iter().sum::<i64>(), ms.iter().sum::<i64>());
+        if bytes <= 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis((len as i64 * ms / bytes) as u64)
     }
 
-    #[allow(dead_code)]
-    pub fn reset(&mut self, a: i64, b: i64) {
-        let _ = std::mem::replace(&mut self.v, Box::new([(a, b); 4]));
+    /// how far the audible output lags behind the live edge
+    pub fn latency(&self, len: usize) -> Duration {
+        let (age, at) = self.age;
+        let (_, ms) = self.v[self.v.len() - 1];
+        let lag = age + at.elapsed().as_millis() as i64 - ms + self.buffered(len).as_millis() as i64;
+        Duration::from_millis(lag.max(0) as u64)
     }
 
-    pub fn delay(&self) -> Duration {
-        let (a, b): (Vec<i64>, Vec<i64>) = self.v.iter().cloned().unzip();
-        Duration::from_millis(
-            ((a.iter().sum::<i64>() as f64 / 24.8_f64) // 5 sec 31k
-                + (b.iter().sum::<i64>() as f64 / 4_f64)
-                - 5500_f64) as u64, // adjust
-        )
+    /// playlist refresh interval, shortened while the buffer is under target
+    pub fn delay(&self, len: usize) -> Duration {
+        let target = self.target.as_millis() as i64;
+        let ahead =
+            self.buffered(len).as_millis() as i64 - self.buffer_target().as_millis() as i64;
+        Duration::from_millis((target + ahead).clamp(target / 2, target * 3) as u64)
     }
 }
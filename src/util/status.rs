@@ -0,0 +1,64 @@
+use crate::util::store;
+use anyhow::Error;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// state of the player for status bars, written to `--status-file` and
+/// served to `radico status`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NowPlaying {
+    pub station: Option<String>,
+    pub station_id: Option<String>,
+    pub title: Option<String>,
+    /// program start and end, local time as YYYY-MM-DDThh:mm:ss
+    pub start: Option<String>,
+    pub end: Option<String>,
+    /// 0-9
+    pub volume: u8,
+    pub buffer_bytes: usize,
+    pub buffer_secs: f32,
+    /// buffered audio relative to the buffer target
+    pub buffer_fill: f32,
+    /// online, degraded or offline
+    pub connection: String,
+    pub stopped: bool,
+}
+
+/// `--status-file`, rewritten at most once a second while the state changes
+pub struct StatusFile {
+    path: Option<PathBuf>,
+    written: Instant,
+    dirty: bool,
+}
+
+impl StatusFile {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        StatusFile {
+            path,
+            written: Instant::now() - WRITE_INTERVAL,
+            dirty: false,
+        }
+    }
+
+    pub fn update(&mut self, now: &NowPlaying, changed: bool) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        self.dirty |= changed;
+        if !self.dirty || self.written.elapsed() < WRITE_INTERVAL {
+            return;
+        }
+        let res = serde_json::to_vec(now)
+            .map_err(Error::from)
+            .and_then(|x| store::write_atomic(path, &x));
+        if let Err(e) = res {
+            error!("status file {:?}: {:?}\r", path, e);
+        }
+        self.written = Instant::now();
+        self.dirty = false;
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+/// per-user state directory, `$XDG_STATE_HOME/radico` or `%APPDATA%\radico`
+pub fn dir() -> Option<PathBuf> {
+    #[cfg(windows)]
+    let base = env::var_os("APPDATA").map(PathBuf::from);
+    #[cfg(not(windows))]
+    let base = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|x| PathBuf::from(x).join(".local/state")));
+    base.map(|x| x.join("radico"))
+}
+
+/// read a JSON document, falling back on the default when missing or invalid
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    dir()
+        .and_then(|x| fs::read(x.join(name)).ok())
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_default()
+}
+
+/// write a JSON document, replacing the previous one atomically
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let Some(dir) = dir() else {
+        return Ok(());
+    };
+    // the name may hold a subdirectory
+    let path = dir.join(name);
+    fs::create_dir_all(path.parent().unwrap_or(&dir))?;
+    write_atomic(&path, &serde_json::to_vec_pretty(value)?)
+}
+
+/// write through a temporary file in the same directory and rename it over
+/// `path`, so readers never see a partial file
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,434 @@
+use crate::api::strip_html;
+use crate::api::xml::Prog;
+use crate::util::store;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use itertools::Itertools;
+use std::fs;
+use std::path::Path;
+
+/// metadata of a recorded program
+#[derive(Debug, Default, Clone)]
+pub struct Tags {
+    pub station: String,
+    pub program: String,
+    /// `Prog.pfm`
+    pub performer: String,
+    pub genre: String,
+    /// page of the program
+    pub url: String,
+    /// `Prog.info`, or else `Prog.desc`, without markup
+    pub info: String,
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    /// PNG or JPEG
+    pub logo: Option<Vec<u8>>,
+}
+
+impl Tags {
+    pub fn new(station: &str, prog: &Prog) -> Self {
+        let time = |x: &str| NaiveDateTime::parse_from_str(x, "%Y%m%d%H%M%S").ok();
+        let text = |x: &str| strip_html(x).split_whitespace().join(" ");
+        let info = match text(&prog.info) {
+            x if x.is_empty() => text(&prog.desc),
+            x => x,
+        };
+        Tags {
+            station: station.to_string(),
+            program: prog.title.to_owned(),
+            performer: prog.pfm.trim().to_string(),
+            genre: prog.genres().join("/"),
+            url: prog.url.trim().to_string(),
+            info,
+            start: time(&prog.ft),
+            end: time(&prog.to),
+            logo: None,
+        }
+    }
+
+    /// the program title and its date, so episodes of a series differ
+    fn title(&self) -> String {
+        match self.start {
+            Some(start) => format!("{} {}", self.program, start.format("%Y-%m-%d %H:%M")),
+            None => self.program.to_owned(),
+        }
+    }
+
+    /// performers, or the station when the guide names none
+    fn artist(&self) -> &str {
+        if self.performer.is_empty() { &self.station } else { &self.performer }
+    }
+
+    fn time(x: Option<NaiveDateTime>) -> Option<String> {
+        x.map(|x| x.format("%Y-%m-%dT%H:%M:%S").to_string())
+    }
+
+    fn png(&self) -> bool {
+        self.logo.as_ref().is_some_and(|x| x.starts_with(b"\x89PNG"))
+    }
+}
+
+/// tag a recording in place, ID3v2 for ADTS and an ilst for MP4/M4A
+pub fn write(path: &Path, tags: &Tags) -> Result<()> {
+    let buf = fs::read(path)?;
+    let out = match buf.get(4..8) {
+        Some(b"ftyp") => m4a(&buf, tags)?,
+        _ => [id3v2(tags).as_slice(), strip_id3(&buf)].concat(),
+    };
+    store::write_atomic(path, &out)
+}
+
+/// skip the ID3v2 tags in front of ADTS data
+pub fn strip_id3(mut buf: &[u8]) -> &[u8] {
+    while buf.len() >= 10 && &buf[..3] == b"ID3" {
+        // syncsafe size, plus the footer if flagged
+        let size = buf[6..10].iter().fold(0, |acc, &x| acc << 7 | (x & 0x7f) as usize);
+        let footer = if buf[5] & 0x10 != 0 { 10 } else { 0 };
+        buf = buf.get(10 + size + footer..).unwrap_or_default();
+    }
+    buf
+}
+
+fn syncsafe(n: usize) -> [u8; 4] {
+    [(n >> 21) as u8 & 0x7f, (n >> 14) as u8 & 0x7f, (n >> 7) as u8 & 0x7f, n as u8 & 0x7f]
+}
+
+/// ID3v2.4 tag with UTF-8 text frames
+pub fn id3v2(tags: &Tags) -> Vec<u8> {
+    const UTF8: u8 = 3;
+    let mut frames = Vec::new();
+    let mut frame = |id: &[u8; 4], data: Vec<u8>| {
+        frames.extend_from_slice(id);
+        frames.extend_from_slice(&syncsafe(data.len()));
+        frames.extend_from_slice(&[0, 0]);
+        frames.extend(data);
+    };
+    let text = |x: &str| [&[UTF8], x.as_bytes()].concat();
+    let txxx = |desc: &str, x: &str| [&[UTF8], desc.as_bytes(), &[0], x.as_bytes()].concat();
+
+    frame(b"TIT2", text(&tags.title()));
+    frame(b"TALB", text(&tags.program));
+    frame(b"TPE1", text(tags.artist()));
+    frame(b"TPE2", text(&tags.station));
+    frame(b"TPUB", text(&tags.station));
+    if !tags.genre.is_empty() {
+        frame(b"TCON", text(&tags.genre));
+    }
+    if !tags.url.is_empty() {
+        // empty description, the url itself is latin-1
+        frame(b"WXXX", [&[UTF8, 0], tags.url.as_bytes()].concat());
+    }
+    if let Some(start) = Tags::time(tags.start) {
+        frame(b"TDRC", text(&start));
+        frame(b"TXXX", txxx("START", &start));
+    }
+    if let Some(end) = Tags::time(tags.end) {
+        frame(b"TXXX", txxx("END", &end));
+    }
+    if let (Some(start), Some(end)) = (tags.start, tags.end) {
+        frame(b"TLEN", text(&(end - start).num_milliseconds().to_string()));
+    }
+    if !tags.info.is_empty() {
+        // language, empty description
+        frame(b"COMM", [&[UTF8], b"jpn".as_slice(), &[0], tags.info.as_bytes()].concat());
+    }
+    if let Some(logo) = &tags.logo {
+        let mime: &[u8] = if tags.png() { b"image/png" } else { b"image/jpeg" };
+        // front cover, empty description
+        frame(b"APIC", [&[UTF8], mime, &[0, 3, 0], logo.as_slice()].concat());
+    }
+
+    let mut tag = b"ID3\x04\x00\x00".to_vec();
+    tag.extend_from_slice(&syncsafe(frames.len()));
+    tag.extend(frames);
+    tag
+}
+
+fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    [&(8 + payload.len() as u32).to_be_bytes(), kind.as_slice(), payload].concat()
+}
+
+/// iTunes style `udta/meta/ilst`
+fn ilst(tags: &Tags) -> Vec<u8> {
+    const UTF8: u32 = 1;
+    const JPEG: u32 = 13;
+    const PNG: u32 = 14;
+    let data = |kind: u32, x: &[u8]| {
+        mp4_box(b"data", &[&kind.to_be_bytes(), [0; 4].as_slice(), x].concat())
+    };
+    let text = |kind: &[u8; 4], x: &str| mp4_box(kind, &data(UTF8, x.as_bytes()));
+    let freeform = |name: &str, x: &str| {
+        mp4_box(
+            b"----",
+            &[
+                mp4_box(b"mean", &[[0; 4].as_slice(), b"com.apple.iTunes"].concat()),
+                mp4_box(b"name", &[[0; 4].as_slice(), name.as_bytes()].concat()),
+                data(UTF8, x.as_bytes()),
+            ]
+            .concat(),
+        )
+    };
+
+    let mut items = vec![
+        text(b"\xa9nam", &tags.title()),
+        text(b"\xa9alb", &tags.program),
+        text(b"\xa9ART", tags.artist()),
+        text(b"aART", &tags.station),
+    ];
+    if !tags.genre.is_empty() {
+        items.push(text(b"\xa9gen", &tags.genre));
+    }
+    if !tags.url.is_empty() {
+        items.push(freeform("URL", &tags.url));
+    }
+    if let Some(start) = Tags::time(tags.start) {
+        items.push(text(b"\xa9day", &start));
+        items.push(freeform("START", &start));
+    }
+    if let Some(end) = Tags::time(tags.end) {
+        items.push(freeform("END", &end));
+    }
+    if !tags.info.is_empty() {
+        items.push(text(b"\xa9cmt", &tags.info));
+        items.push(text(b"desc", &tags.info));
+    }
+    if let Some(logo) = &tags.logo {
+        items.push(mp4_box(b"covr", &data(if tags.png() { PNG } else { JPEG }, logo)));
+    }
+
+    // metadata handler: version and flags, pre_defined, "mdir", reserved, name
+    let hdlr = mp4_box(b"hdlr", &[[0; 8].as_slice(), b"mdirappl", &[0; 9]].concat());
+    let meta = mp4_box(b"meta", &[[0; 4].as_slice(), &hdlr, &mp4_box(b"ilst", &items.concat())].concat());
+    mp4_box(b"udta", &meta)
+}
+
+/// boxes in `buf` as (type, start, header length, end)
+fn boxes(buf: &[u8]) -> Vec<([u8; 4], usize, usize, usize)> {
+    let mut v = Vec::new();
+    let mut i = 0;
+    while i + 8 <= buf.len() {
+        let size = u32::from_be_bytes(buf[i..i + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = buf[i + 4..i + 8].try_into().unwrap();
+        let (size, header) = match size {
+            0 => (buf.len() - i, 8),
+            1 if i + 16 <= buf.len() => {
+                (u64::from_be_bytes(buf[i + 8..i + 16].try_into().unwrap()) as usize, 16)
+            },
+            _ => (size, 8),
+        };
+        if size < header || i + size > buf.len() {
+            break;
+        }
+        v.push((kind, i, header, i + size));
+        i += size;
+    }
+    v
+}
+
+/// move the chunk offsets in `stco`/`co64` under a container by `delta`
+fn shift_offsets(buf: &mut [u8], delta: i64) {
+    for (kind, start, header, end) in boxes(buf) {
+        let body = start + header;
+        match &kind {
+            b"trak" | b"mdia" | b"minf" | b"stbl" => shift_offsets(&mut buf[body..end], delta),
+            b"stco" | b"co64" => {
+                let width = if &kind == b"stco" { 4 } else { 8 };
+                let Some(count) = buf.get(body + 4..body + 8) else { continue };
+                let count = u32::from_be_bytes(count.try_into().unwrap()) as usize;
+                for n in 0..count {
+                    let at = body + 8 + n * width;
+                    let Some(x) = buf.get_mut(at..at + width) else { break };
+                    if width == 4 {
+                        let v = u32::from_be_bytes(x.try_into().unwrap()) as i64 + delta;
+                        x.copy_from_slice(&(v as u32).to_be_bytes());
+                    } else {
+                        let v = u64::from_be_bytes(x.try_into().unwrap()) as i64 + delta;
+                        x.copy_from_slice(&(v as u64).to_be_bytes());
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+/// the file with its `moov/udta` replaced by our tags
+fn m4a(buf: &[u8], tags: &Tags) -> Result<Vec<u8>> {
+    let top = boxes(buf);
+    let (_, start, header, end) = *top
+        .iter()
+        .find(|x| &x.0 == b"moov")
+        .ok_or(crate::errors::RadicoError::SegmentError("no moov box".to_string()))?;
+
+    let mut children = boxes(&buf[start + header..end])
+        .into_iter()
+        .filter(|x| &x.0 != b"udta")
+        .map(|(_, s, _, e)| buf[start + header + s..start + header + e].to_vec())
+        .concat();
+    children.extend(ilst(tags));
+    let mut moov = mp4_box(b"moov", &children);
+
+    // media data after moov moves by the change in size
+    if top.iter().any(|x| &x.0 == b"mdat" && x.1 > start) {
+        let delta = moov.len() as i64 - (end - start) as i64;
+        shift_offsets(&mut moov[8..], delta);
+    }
+    Ok([&buf[..start], moov.as_slice(), &buf[end..]].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> Tags {
+        let prog = Prog {
+            ft: "20261017060000".to_string(),
+            to: "20261017083000".to_string(),
+            title: "森本毅郎・スタンバイ!".to_string(),
+            pfm: " 森本毅郎 ".to_string(),
+            info: "<p>ニュース\n<b>ワイド</b></p>".to_string(),
+            url: "https://www.tbsradio.jp/stand-by/".to_string(),
+            ..Default::default()
+        };
+        Tags::new("TBSラジオ", &prog)
+    }
+
+    /// the frames of a tag as (id, body)
+    fn frames(tag: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let size = |x: &[u8]| x.iter().fold(0, |acc, &x| acc << 7 | x as usize);
+        let mut v = Vec::new();
+        let mut rest = &tag[10..10 + size(&tag[6..10])];
+        while !rest.is_empty() {
+            let len = size(&rest[4..8]);
+            v.push((String::from_utf8(rest[..4].to_vec()).unwrap(), rest[10..10 + len].to_vec()));
+            rest = &rest[10 + len..];
+        }
+        v
+    }
+
+    fn frame<'a>(frames: &'a [(String, Vec<u8>)], id: &str) -> Vec<&'a [u8]> {
+        frames.iter().filter(|x| x.0 == id).map(|x| x.1.as_slice()).collect()
+    }
+
+    #[test]
+    fn header() {
+        let tag = id3v2(&tags());
+        assert_eq!(&tag[..6], b"ID3\x04\x00\x00");
+        assert!(tag[6..10].iter().all(|x| x & 0x80 == 0));
+        assert_eq!(syncsafe(0x0fff_ffff), [0x7f; 4]);
+        assert_eq!(syncsafe(200), [0, 0, 1, 0x48]);
+    }
+
+    #[test]
+    fn text_frames() {
+        let frames = frames(&id3v2(&tags()));
+        assert_eq!(frame(&frames, "TIT2"), [&[&[3], "森本毅郎・スタンバイ! 2026-10-17 06:00".as_bytes()].concat()]);
+        assert_eq!(frame(&frames, "TALB"), [&[&[3], "森本毅郎・スタンバイ!".as_bytes()].concat()]);
+        assert_eq!(frame(&frames, "TPE1"), [&[&[3], "森本毅郎".as_bytes()].concat()]);
+        assert_eq!(frame(&frames, "TPUB"), [&[&[3], "TBSラジオ".as_bytes()].concat()]);
+        assert_eq!(frame(&frames, "TDRC"), [b"\x032026-10-17T06:00:00"]);
+        assert_eq!(frame(&frames, "TLEN"), [b"\x039000000"]);
+        assert_eq!(frame(&frames, "TXXX"), [b"\x03START\x002026-10-17T06:00:00".as_slice(), b"\x03END\x002026-10-17T08:30:00"]);
+        assert_eq!(frame(&frames, "WXXX"), [b"\x03\x00https://www.tbsradio.jp/stand-by/"]);
+        assert_eq!(frame(&frames, "COMM"), [&[b"\x03jpn\x00", "ニュース ワイド".as_bytes()].concat()]);
+        // no genre in the guide, no frame
+        assert!(frame(&frames, "TCON").is_empty());
+        assert!(frame(&frames, "APIC").is_empty());
+    }
+
+    #[test]
+    fn logo() {
+        let mut tags = tags();
+        tags.performer.clear();
+        tags.logo = Some(b"\x89PNG\r\n\x1a\n".to_vec());
+        let frames = frames(&id3v2(&tags));
+        // the station stands in for missing performers
+        assert_eq!(frame(&frames, "TPE1"), [&[&[3], "TBSラジオ".as_bytes()].concat()]);
+        assert_eq!(frame(&frames, "APIC"), [b"\x03image/png\x00\x03\x00\x89PNG\r\n\x1a\n"]);
+    }
+
+    /// the body of the box at `path`, each step the first of its type
+    fn find<'a>(buf: &'a [u8], path: &[&[u8; 4]]) -> &'a [u8] {
+        path.iter().fold(buf, |buf, kind| {
+            let (_, start, header, end) = boxes(buf).into_iter().find(|x| &&x.0 == kind).unwrap();
+            &buf[start + header..end]
+        })
+    }
+
+    /// the chunk offset of a track's `stco` or `co64`
+    fn offset(moov: &[u8], n: usize) -> usize {
+        let trak = boxes(moov).into_iter().filter(|x| &x.0 == b"trak").nth(n).unwrap();
+        let stbl = find(&moov[trak.1 + trak.2..trak.3], &[b"mdia", b"minf", b"stbl"]);
+        let (kind, start, header, _) = boxes(stbl)[0];
+        let at = start + header + 8;
+        match &kind {
+            b"stco" => u32::from_be_bytes(stbl[at..at + 4].try_into().unwrap()) as usize,
+            _ => u64::from_be_bytes(stbl[at..at + 8].try_into().unwrap()) as usize,
+        }
+    }
+
+    #[test]
+    fn ilst_items() {
+        let udta = ilst(&tags());
+        let ilst = find(&udta, &[b"udta", b"meta"]);
+        // version and flags before the children of meta
+        let ilst = find(&ilst[4..], &[b"ilst"]);
+        let data = find(ilst, &[b"\xa9nam", b"data"]);
+        assert_eq!(data, [b"\x00\x00\x00\x01\x00\x00\x00\x00".as_slice(), "森本毅郎・スタンバイ! 2026-10-17 06:00".as_bytes()].concat());
+        assert_eq!(&find(ilst, &[b"aART", b"data"])[8..], "TBSラジオ".as_bytes());
+        assert_eq!(&find(ilst, &[b"\xa9cmt", b"data"])[8..], "ニュース ワイド".as_bytes());
+        let url = find(ilst, &[b"----"]);
+        assert_eq!(&find(url, &[b"name"])[4..], b"URL");
+        assert_eq!(&find(url, &[b"data"])[8..], b"https://www.tbsradio.jp/stand-by/");
+    }
+
+    #[test]
+    fn m4a_offsets() {
+        let buf = include_bytes!("../../tests/fixtures/tagged.m4a");
+        let out = m4a(buf, &tags()).unwrap();
+        let kinds = boxes(&out).iter().map(|x| x.0).collect::<Vec<_>>();
+        assert_eq!(kinds, [*b"ftyp", *b"moov", *b"mdat"]);
+
+        // the old udta is replaced, the tracks stay
+        let moov = find(&out, &[b"moov"]);
+        assert_eq!(boxes(moov).iter().filter(|x| &x.0 == b"udta").count(), 1);
+        assert!(!out.windows(9).any(|x| x == b"old title"));
+        assert_eq!(find(moov, &[b"udta"]), find(&ilst(&tags()), &[b"udta"]));
+
+        // both chunk offset widths still point at the same media data
+        let mdat = find(buf, &[b"mdat"]);
+        assert_eq!(&out[offset(moov, 0)..][..16], &mdat[..16]);
+        assert_eq!(&out[offset(moov, 1)..][..16], &mdat[16..]);
+        assert_eq!(offset(moov, 1) - offset(moov, 0), 16);
+        assert!(offset(moov, 0) > offset(find(buf, &[b"moov"]), 0));
+    }
+
+    #[test]
+    fn write_in_place() {
+        let dir = std::env::temp_dir().join(format!("radico-tags-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (adts, m4a) = (dir.join("a.aac"), dir.join("a.m4a"));
+        fs::write(&adts, [0xff, 0xf1, 0x50, 0x80]).unwrap();
+        fs::write(&m4a, include_bytes!("../../tests/fixtures/tagged.m4a")).unwrap();
+
+        // tagging again replaces the tag rather than stacking another
+        for _ in 0..2 {
+            write(&adts, &tags()).unwrap();
+            write(&m4a, &tags()).unwrap();
+        }
+        assert_eq!(fs::read(&adts).unwrap(), [id3v2(&tags()), vec![0xff, 0xf1, 0x50, 0x80]].concat());
+        let buf = fs::read(&m4a).unwrap();
+        assert_eq!(buf, self::m4a(include_bytes!("../../tests/fixtures/tagged.m4a"), &tags()).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn strip() {
+        let adts = [0xff, 0xf1, 0x50, 0x80];
+        let tagged = [id3v2(&tags()), id3v2(&Tags::default()), adts.to_vec()].concat();
+        assert_eq!(strip_id3(&tagged), adts);
+        assert_eq!(strip_id3(&adts), adts);
+        // a size past the end
+        assert!(strip_id3(b"ID3\x04\x00\x00\x00\x00\x01\x00").is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::api::guide::jst_now;
+use crate::errors::RadicoError::TimerError;
+use anyhow::{Error, Result};
+use chrono::NaiveDateTime;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// choices cycled by the `s` key
+const CYCLE: [Timer; 5] = [
+    Timer::Off,
+    Timer::After(Duration::from_secs(15 * 60)),
+    Timer::After(Duration::from_secs(30 * 60)),
+    Timer::After(Duration::from_secs(60 * 60)),
+    Timer::EndOfProgram,
+];
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum Timer {
+    #[default]
+    Off,
+    After(Duration),
+    /// when the program on air ends, as heard
+    EndOfProgram,
+}
+
+impl FromStr for Timer {
+    type Err = Error;
+
+    /// `off`, `end`, or a duration such as `45m`, `1h30m`, `90s`; bare
+    /// numbers are minutes, none of them zero
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => return Ok(Timer::Off),
+            "end" => return Ok(Timer::EndOfProgram),
+            _ => {},
+        }
+        if let Ok(min) = s.parse::<u64>() {
+            if min == 0 {
+                return Err(Error::from(TimerError(s.to_string())));
+            }
+            return Ok(Timer::After(Duration::from_secs(min * 60)));
+        }
+
+        let mut secs = 0;
+        let mut n = String::new();
+        for c in s.chars() {
+            if c.is_ascii_digit() {
+                n.push(c);
+                continue;
+            }
+            let unit = match c {
+                'h' => 3600,
+                'm' => 60,
+                's' => 1,
+                _ => return Err(Error::from(TimerError(s.to_string()))),
+            };
+            secs += n.parse::<u64>().map_err(|_| TimerError(s.to_string()))? * unit;
+            n.clear();
+        }
+        if !n.is_empty() || secs == 0 {
+            return Err(Error::from(TimerError(s.to_string())));
+        }
+        Ok(Timer::After(Duration::from_secs(secs)))
+    }
+}
+
+impl fmt::Display for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Timer::Off => write!(f, "off"),
+            Timer::After(d) => {
+                // as it would be given, 1h30m or 90s as 1m30s
+                let secs = d.as_secs();
+                let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+                for (n, unit) in [(h, "h"), (m, "m"), (s, "s")] {
+                    if n > 0 {
+                        write!(f, "{}{}", n, unit)?;
+                    }
+                }
+                if secs == 0 {
+                    write!(f, "0s")?;
+                }
+                Ok(())
+            },
+            Timer::EndOfProgram => write!(f, "end of program"),
+        }
+    }
+}
+
+/// sleep timer counting from when it was set
+#[derive(Debug, Default)]
+pub struct SleepTimer {
+    timer: Timer,
+    since: Option<Instant>,
+    /// end of the program on air when the timer was set, kept so the timer
+    /// does not move on to the next program once `Api` refreshes it
+    end: Option<NaiveDateTime>,
+}
+
+impl SleepTimer {
+    pub fn new(timer: Timer) -> Self {
+        let mut sleep = SleepTimer::default();
+        sleep.set(timer);
+        sleep
+    }
+
+    pub fn set(&mut self, timer: Timer) {
+        self.timer = timer;
+        self.since = Some(Instant::now());
+        self.end = None;
+    }
+
+    /// advance to the next choice, returns it
+    pub fn cycle(&mut self) -> Timer {
+        let i = CYCLE.iter().position(|x| *x == self.timer).map_or(0, |x| x + 1);
+        self.set(CYCLE[i % CYCLE.len()]);
+        self.timer
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.timer == Timer::Off
+    }
+
+    /// time left, given when the current program ends, if known, and how far
+    /// playback lags behind live
+    pub fn remaining(
+        &mut self,
+        program_end: Option<NaiveDateTime>,
+        latency: Duration,
+    ) -> Option<Duration> {
+        match self.timer {
+            Timer::Off => None,
+            Timer::After(d) => Some(d.saturating_sub(self.since?.elapsed())),
+            Timer::EndOfProgram => {
+                let end = *self.end.get_or_insert(program_end?);
+                let heard = jst_now() - latency;
+                Some((end - heard).to_std().unwrap_or_default())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn after(secs: u64) -> Timer { Timer::After(Duration::from_secs(secs)) }
+
+    #[test]
+    fn parse() {
+        assert_eq!("45m".parse::<Timer>().unwrap(), after(45 * 60));
+        assert_eq!("1h30m".parse::<Timer>().unwrap(), after(90 * 60));
+        assert_eq!("90s".parse::<Timer>().unwrap(), after(90));
+        assert_eq!("1h0m5s".parse::<Timer>().unwrap(), after(3605));
+        // bare numbers are minutes
+        assert_eq!("30".parse::<Timer>().unwrap(), after(30 * 60));
+        assert_eq!("off".parse::<Timer>().unwrap(), Timer::Off);
+        assert_eq!("end".parse::<Timer>().unwrap(), Timer::EndOfProgram);
+
+        for s in ["0", "0m", "1x", "h", "1h30", "", "-5", "1.5h"] {
+            assert!(s.parse::<Timer>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(after(90).to_string(), "1m30s");
+        assert_eq!(after(45 * 60).to_string(), "45m");
+        assert_eq!(after(90 * 60).to_string(), "1h30m");
+        assert_eq!(after(3605).to_string(), "1h5s");
+        assert_eq!(after(0).to_string(), "0s");
+        assert_eq!(Timer::EndOfProgram.to_string(), "end of program");
+        // what is shown parses back to the same timer
+        for timer in CYCLE.iter().chain(&[after(90), after(3605)]) {
+            if let Timer::After(_) = timer {
+                assert_eq!(timer.to_string().parse::<Timer>().unwrap(), *timer);
+            }
+        }
+    }
+}